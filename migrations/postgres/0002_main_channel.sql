@@ -0,0 +1,2 @@
+-- discord ChannelId of the main messaging channel
+ALTER TABLE guilds ADD COLUMN IF NOT EXISTS main_channel_id bigint DEFAULT NULL;
@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS guild_settings (
+    -- discord GuildId
+    guild_id bigint NOT NULL,
+
+    -- every NULL setting uses the value from config.ron
+
+    -- how many roles a single user can own
+    max_owned_roles int DEFAULT NULL,
+    -- reply with the original content of edited messages
+    anti_censor boolean DEFAULT NULL,
+    -- repost deleted messages
+    delete_logging boolean DEFAULT NULL,
+    -- reply "u" when two different users say "u" in a row
+    u_echo boolean DEFAULT NULL,
+    -- new roles are displayed separately
+    hoist boolean DEFAULT NULL,
+    -- new roles can be mentioned by anyone
+    mentionable boolean DEFAULT NULL,
+
+    PRIMARY KEY (guild_id),
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS role_limits (
+    -- discord GuildId
+    guild_id bigint NOT NULL,
+    -- discord RoleId of any role, not only the ones in `roles`
+    role_id bigint NOT NULL,
+    -- members with this role can own this many roles, 0 is unlimited
+    max_owned_roles int NOT NULL,
+
+    PRIMARY KEY (guild_id, role_id),
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+-- unix timestamp when the owned role expires, NULL never expires
+ALTER TABLE roles ADD COLUMN IF NOT EXISTS expires_at bigint DEFAULT NULL;
+-- the owner has been warned about the upcoming expiry
+ALTER TABLE roles ADD COLUMN IF NOT EXISTS expiry_warned boolean NOT NULL DEFAULT FALSE;
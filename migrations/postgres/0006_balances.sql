@@ -0,0 +1,2 @@
+-- role currency of the user in this guild
+ALTER TABLE users ADD COLUMN IF NOT EXISTS balance bigint NOT NULL DEFAULT 0;
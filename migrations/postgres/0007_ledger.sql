@@ -0,0 +1,35 @@
+CREATE TABLE IF NOT EXISTS ledger (
+    -- insertion order
+    entry_id bigserial NOT NULL,
+    -- discord GuildId
+    guild_id bigint NOT NULL,
+    -- discord UserId whose balance changed
+    user_id bigint NOT NULL,
+    -- signed change of the balance
+    amount bigint NOT NULL,
+    -- kind of the transaction, like `transfer` or `create_role`
+    reason varchar(32) NOT NULL,
+    -- discord RoleId of the role that was paid for
+    role_id bigint DEFAULT NULL,
+    -- discord UserId of the other side of a transfer or the admin of a grant
+    other_user_id bigint DEFAULT NULL,
+    -- unix timestamp
+    created_at bigint NOT NULL DEFAULT EXTRACT(EPOCH FROM now())::bigint,
+
+    PRIMARY KEY (entry_id),
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS ledger_user ON ledger (guild_id, user_id, entry_id);
+
+-- entries are append-only, they only go away with their guild
+CREATE OR REPLACE FUNCTION ledger_immutable() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'ledger entries cannot be modified';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS ledger_immutable ON ledger;
+CREATE TRIGGER ledger_immutable
+BEFORE UPDATE ON ledger
+FOR EACH ROW EXECUTE FUNCTION ledger_immutable();
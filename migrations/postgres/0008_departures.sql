@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS departures (
+    -- discord GuildId
+    guild_id bigint NOT NULL,
+    -- discord UserId of a role owner who left the guild
+    user_id bigint NOT NULL,
+    -- unix timestamp
+    left_at bigint NOT NULL,
+
+    PRIMARY KEY (guild_id, user_id),
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE
+);
+
+-- delete the roles of owners who left instead of orphaning them
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS delete_departed_roles boolean DEFAULT NULL;
@@ -0,0 +1,2 @@
+-- unix timestamp of the last full sync with discord
+ALTER TABLE guilds ADD COLUMN IF NOT EXISTS synced_at bigint DEFAULT NULL;
@@ -0,0 +1,2 @@
+-- unix timestamp of when the bot was removed from the guild
+ALTER TABLE guilds ADD COLUMN IF NOT EXISTS left_at bigint DEFAULT NULL;
@@ -0,0 +1,5 @@
+-- which roles rejoining members get back, `on`, `sticky` or `off`
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS restore_roles varchar(16) DEFAULT NULL;
+
+-- NULL follows restore_roles, TRUE and FALSE override it
+ALTER TABLE roles ADD COLUMN IF NOT EXISTS sticky boolean DEFAULT NULL;
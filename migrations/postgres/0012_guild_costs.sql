@@ -0,0 +1,4 @@
+-- price of /create, 0 is free
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS role_cost int DEFAULT NULL;
+-- price of /extend, 0 is free
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS extend_cost int DEFAULT NULL;
@@ -0,0 +1,2 @@
+-- currency earned by sending a message, 0 turns rewards off
+ALTER TABLE guild_settings ADD COLUMN IF NOT EXISTS message_reward int DEFAULT NULL;
@@ -0,0 +1,35 @@
+CREATE TABLE IF NOT EXISTS guilds (
+    guild_id INTEGER NOT NULL,
+
+    PRIMARY KEY (guild_id)
+);
+
+CREATE TABLE IF NOT EXISTS roles (
+    role_id INTEGER NOT NULL,
+    guild_id INTEGER NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    owner_user_id INTEGER DEFAULT NULL,
+
+    UNIQUE (guild_id, name),
+    PRIMARY KEY (role_id, guild_id),
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS users (
+    user_id INTEGER NOT NULL,
+    guild_id INTEGER NOT NULL,
+
+    PRIMARY KEY (user_id, guild_id),
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS user_roles (
+    user_id INTEGER NOT NULL,
+    guild_id INTEGER NOT NULL,
+    role_id INTEGER NOT NULL,
+
+    PRIMARY KEY (user_id, guild_id, role_id),
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id, guild_id) REFERENCES users (user_id, guild_id) ON DELETE CASCADE,
+    FOREIGN KEY (role_id, guild_id) REFERENCES roles (role_id, guild_id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- discord ChannelId of the main messaging channel
+ALTER TABLE guilds ADD COLUMN main_channel_id INTEGER DEFAULT NULL;
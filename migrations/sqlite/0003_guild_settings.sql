@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS guild_settings (
+    guild_id INTEGER NOT NULL,
+    max_owned_roles INTEGER DEFAULT NULL,
+    anti_censor BOOLEAN DEFAULT NULL,
+    delete_logging BOOLEAN DEFAULT NULL,
+    u_echo BOOLEAN DEFAULT NULL,
+    hoist BOOLEAN DEFAULT NULL,
+    mentionable BOOLEAN DEFAULT NULL,
+
+    PRIMARY KEY (guild_id),
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE
+);
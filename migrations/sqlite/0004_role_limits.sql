@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS role_limits (
+    guild_id INTEGER NOT NULL,
+    role_id INTEGER NOT NULL,
+    max_owned_roles INTEGER NOT NULL,
+
+    PRIMARY KEY (guild_id, role_id),
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+ALTER TABLE roles ADD COLUMN expires_at INTEGER DEFAULT NULL;
+ALTER TABLE roles ADD COLUMN expiry_warned BOOLEAN NOT NULL DEFAULT FALSE;
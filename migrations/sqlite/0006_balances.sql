@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN balance INTEGER NOT NULL DEFAULT 0;
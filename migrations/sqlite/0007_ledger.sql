@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS ledger (
+    entry_id INTEGER NOT NULL,
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    amount INTEGER NOT NULL,
+    reason VARCHAR(32) NOT NULL,
+    role_id INTEGER DEFAULT NULL,
+    other_user_id INTEGER DEFAULT NULL,
+    created_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+
+    PRIMARY KEY (entry_id),
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS ledger_user ON ledger (guild_id, user_id, entry_id);
+
+CREATE TRIGGER IF NOT EXISTS ledger_immutable
+BEFORE UPDATE ON ledger
+BEGIN
+    SELECT RAISE(ABORT, 'ledger entries cannot be modified');
+END;
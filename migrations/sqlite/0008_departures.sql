@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS departures (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    left_at INTEGER NOT NULL,
+
+    PRIMARY KEY (guild_id, user_id),
+    FOREIGN KEY (guild_id) REFERENCES guilds (guild_id) ON DELETE CASCADE
+);
+
+ALTER TABLE guild_settings ADD COLUMN delete_departed_roles BOOLEAN DEFAULT NULL;
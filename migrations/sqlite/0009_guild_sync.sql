@@ -0,0 +1 @@
+ALTER TABLE guilds ADD COLUMN synced_at INTEGER DEFAULT NULL;
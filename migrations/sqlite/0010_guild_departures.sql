@@ -0,0 +1 @@
+ALTER TABLE guilds ADD COLUMN left_at INTEGER DEFAULT NULL;
@@ -0,0 +1,3 @@
+ALTER TABLE guild_settings ADD COLUMN restore_roles VARCHAR(16) DEFAULT NULL;
+
+ALTER TABLE roles ADD COLUMN sticky BOOLEAN DEFAULT NULL;
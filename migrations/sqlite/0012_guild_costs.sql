@@ -0,0 +1,2 @@
+ALTER TABLE guild_settings ADD COLUMN role_cost INTEGER DEFAULT NULL;
+ALTER TABLE guild_settings ADD COLUMN extend_cost INTEGER DEFAULT NULL;
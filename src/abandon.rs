@@ -0,0 +1,52 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, GuildId,
+    ResolvedOption, ResolvedValue,
+};
+
+use crate::Handler;
+
+//
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("abandon")
+        .description("Stop owning a role without deleting it")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Role, "role", "role to be abandoned")
+                .required(true),
+        )
+}
+
+pub async fn run(
+    handler: &Handler,
+    _ctx: &Context,
+    interaction: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let mut options = interaction.data.options();
+
+    let Some(ResolvedOption {
+        value: ResolvedValue::Role(role),
+        ..
+    }) = options.pop()
+    else {
+        return Err("missing role".to_string());
+    };
+
+    let Ok(success) = handler
+        .db
+        .abandon_role(guild_id, role.id, interaction.user.id)
+        .await
+        .inspect_err(|err| tracing::error!("failed to abandon role: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    if !success {
+        return Err("role not owned".to_string());
+    }
+
+    Ok(format!(
+        "role {} is now an orphan, anyone can `/take_ownership` of it",
+        role.name
+    ))
+}
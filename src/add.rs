@@ -44,6 +44,7 @@ pub async fn run(
     };
 
     let Ok(success) = handler
+        .db
         .add_role(guild_id, role.id, user.id)
         .await
         .inspect_err(|err| tracing::error!("failed to add role: {err}"))
@@ -0,0 +1,48 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, GuildId,
+    ResolvedOption, ResolvedValue,
+};
+
+use crate::Handler;
+
+//
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("balance")
+        .description("Show the role currency of a user")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::User,
+            "user",
+            "target user, defaults to yourself",
+        ))
+}
+
+pub async fn run(
+    handler: &Handler,
+    _ctx: &Context,
+    interaction: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let options = interaction.data.options();
+
+    let user_id = if let Some(ResolvedOption {
+        value: ResolvedValue::User(user, _),
+        ..
+    }) = options.first()
+    {
+        user.id
+    } else {
+        interaction.user.id
+    };
+
+    let Ok(balance) = handler
+        .db
+        .balance(guild_id, user_id)
+        .await
+        .inspect_err(|err| tracing::error!("failed to get balance: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    Ok(format!("<@{user_id}> has {balance}"))
+}
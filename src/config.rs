@@ -0,0 +1,288 @@
+use std::{fs, io, path::Path, time::Duration};
+
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use serde::Deserialize;
+use serenity::all::GatewayIntents;
+
+use crate::{DAY_SECONDS, HOUR_SECONDS, WEEK_SECONDS, store::GuildSettings};
+
+//
+
+/// bot tunables, loaded from `config.ron`
+///
+/// every missing field falls back to its default
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// how many roles a single user can own in one guild, 0 is unlimited
+    pub max_owned_roles: u32,
+    /// seconds before an error reply gets deleted
+    pub error_reply_delete_delay: u64,
+    /// how many messages are cached per channel for the delete logging
+    pub max_messages: usize,
+    /// database pool size
+    pub max_connections: u32,
+    /// new roles are displayed separately
+    pub hoist: bool,
+    /// new roles can be mentioned by anyone
+    pub mentionable: bool,
+    /// seconds an owned role lives before it expires, 0 never expires
+    pub role_lifetime: u64,
+    /// seconds before the expiry when the owner gets warned in the main channel
+    pub expiry_warning: u64,
+    /// what happens to an expired role
+    pub expiry_action: ExpiryAction,
+    /// seconds after an owner leaves the guild before their roles are orphaned
+    pub departure_grace_period: u64,
+    /// delete the roles of owners who left instead of orphaning them
+    pub delete_departed_roles: bool,
+    /// which roles a member gets back when they rejoin
+    pub restore_roles: RestoreRoles,
+    /// seconds the data of a guild is kept after the bot is removed from it
+    pub guild_retention: u64,
+    /// seconds between checks for expired roles, departed owners and left guilds
+    pub maintenance_interval: u64,
+    /// price of `/create`, 0 is free
+    pub role_cost: u32,
+    /// price of `/extend`, 0 is free
+    pub extend_cost: u32,
+    /// currency earned by sending a message, 0 turns rewards off
+    pub message_reward: u32,
+    /// seconds before the same user can earn the message reward again
+    pub message_reward_cooldown: u64,
+    /// only log what the startup sync with discord would change, nothing is written
+    pub sync_dry_run: bool,
+    /// how many guilds the startup sync works on at once
+    pub sync_concurrency: usize,
+    /// seconds after a sync before the startup sync does the guild again, 0 always syncs
+    pub sync_cooldown: u64,
+    /// seconds between periodic syncs of every guild, 0 disables them
+    pub resync_interval: u64,
+    /// gateway intent names, like `"GUILD_MEMBERS"`
+    pub intents: Vec<String>,
+    pub features: Features,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    /// reply with the original content of edited messages
+    pub anti_censor: bool,
+    /// repost deleted messages
+    pub delete_logging: bool,
+    /// reply "u" when two different users say "u" in a row
+    pub u_echo: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ExpiryAction {
+    /// remove the owner, anyone can `/take_ownership` of it
+    Orphan,
+    /// delete the discord role
+    Delete,
+}
+
+/// which roles a member gets back when they rejoin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum RestoreRoles {
+    /// every role they had, except the ones marked not sticky
+    On,
+    /// only the roles marked sticky
+    Sticky,
+    /// none
+    Off,
+}
+
+/// [`Config`] combined with the [`GuildSettings`] of one guild
+#[derive(Debug, Clone, Copy)]
+pub struct GuildConfig {
+    pub max_owned_roles: u32,
+    pub anti_censor: bool,
+    pub delete_logging: bool,
+    pub u_echo: bool,
+    pub hoist: bool,
+    pub mentionable: bool,
+    pub delete_departed_roles: bool,
+    pub restore_roles: RestoreRoles,
+    pub role_cost: u32,
+    pub extend_cost: u32,
+    pub message_reward: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_owned_roles: 20,
+            error_reply_delete_delay: 120,
+            max_messages: 256,
+            max_connections: 5,
+            hoist: true,
+            mentionable: true,
+            role_lifetime: 0,
+            expiry_warning: DAY_SECONDS * 3,
+            expiry_action: ExpiryAction::Orphan,
+            departure_grace_period: DAY_SECONDS,
+            delete_departed_roles: false,
+            restore_roles: RestoreRoles::On,
+            guild_retention: WEEK_SECONDS * 4,
+            maintenance_interval: HOUR_SECONDS,
+            role_cost: 0,
+            extend_cost: 0,
+            message_reward: 0,
+            message_reward_cooldown: 60,
+            sync_dry_run: false,
+            sync_concurrency: 2,
+            sync_cooldown: HOUR_SECONDS,
+            resync_interval: DAY_SECONDS,
+            intents: [
+                "GUILDS",
+                "GUILD_MEMBERS",
+                "MESSAGE_CONTENT",
+                "GUILD_MESSAGES",
+                "DIRECT_MESSAGES",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            features: Features::default(),
+        }
+    }
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            anti_censor: true,
+            delete_logging: true,
+            u_echo: true,
+        }
+    }
+}
+
+impl RestoreRoles {
+    pub const ALL: [Self; 3] = [Self::On, Self::Sticky, Self::Off];
+
+    /// the name in `/settings` and in the store
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::On => "on",
+            Self::Sticky => "sticky",
+            Self::Off => "off",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name() == name)
+            .ok_or_else(|| eyre!("unknown restore_roles `{name}`"))
+    }
+}
+
+impl Config {
+    /// load and validate the config, a missing file gives the defaults
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        let config: Self = match fs::read_to_string(path) {
+            Ok(file) => ron::from_str(&file)
+                .wrap_err_with(|| format!("failed to parse {}", path.display()))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                tracing::info!("{} not found, using the defaults", path.display());
+                Self::default()
+            }
+            Err(err) => {
+                return Err(err).wrap_err_with(|| format!("failed to read {}", path.display()));
+            }
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.max_connections == 0 {
+            return Err(eyre!("max_connections has to be at least 1"));
+        }
+
+        if self.sync_concurrency == 0 {
+            return Err(eyre!("sync_concurrency has to be at least 1"));
+        }
+
+        if self.maintenance_interval == 0 {
+            return Err(eyre!("maintenance_interval has to be at least 1"));
+        }
+
+        if self.role_lifetime != 0 && self.expiry_warning >= self.role_lifetime {
+            tracing::warn!("expiry_warning is not shorter than role_lifetime");
+        }
+
+        let intents = self.intents()?;
+        let required = GatewayIntents::GUILDS | GatewayIntents::GUILD_MEMBERS;
+        if !intents.contains(required) {
+            return Err(eyre!("intents have to include GUILDS and GUILD_MEMBERS"));
+        }
+
+        let messages = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+        let uses_messages =
+            self.features.anti_censor || self.features.delete_logging || self.features.u_echo;
+        if uses_messages && !intents.contains(messages) {
+            tracing::warn!(
+                "message features are enabled without the GUILD_MESSAGES and MESSAGE_CONTENT intents"
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn intents(&self) -> Result<GatewayIntents> {
+        self.intents
+            .iter()
+            .try_fold(GatewayIntents::empty(), |acc, name| {
+                GatewayIntents::from_name(name)
+                    .map(|intent| acc | intent)
+                    .ok_or_else(|| eyre!("unknown gateway intent `{name}`"))
+            })
+    }
+
+    /// apply the per guild overrides
+    pub fn resolve(&self, settings: &GuildSettings) -> GuildConfig {
+        GuildConfig {
+            max_owned_roles: settings.max_owned_roles.unwrap_or(self.max_owned_roles),
+            anti_censor: settings.anti_censor.unwrap_or(self.features.anti_censor),
+            delete_logging: settings
+                .delete_logging
+                .unwrap_or(self.features.delete_logging),
+            u_echo: settings.u_echo.unwrap_or(self.features.u_echo),
+            hoist: settings.hoist.unwrap_or(self.hoist),
+            mentionable: settings.mentionable.unwrap_or(self.mentionable),
+            delete_departed_roles: settings
+                .delete_departed_roles
+                .unwrap_or(self.delete_departed_roles),
+            restore_roles: settings.restore_roles.unwrap_or(self.restore_roles),
+            role_cost: settings.role_cost.unwrap_or(self.role_cost),
+            extend_cost: settings.extend_cost.unwrap_or(self.extend_cost),
+            message_reward: settings.message_reward.unwrap_or(self.message_reward),
+        }
+    }
+
+    pub fn error_reply_delete_delay(&self) -> Duration {
+        Duration::from_secs(self.error_reply_delete_delay)
+    }
+
+    pub fn message_reward_cooldown(&self) -> Duration {
+        Duration::from_secs(self.message_reward_cooldown)
+    }
+
+    pub fn maintenance_interval(&self) -> Duration {
+        Duration::from_secs(self.maintenance_interval)
+    }
+
+    pub fn resync_interval(&self) -> Duration {
+        Duration::from_secs(self.resync_interval)
+    }
+
+    /// expiry of a role owned from `now` on, `None` never expires
+    pub fn role_expires_at(&self, now: i64) -> Option<i64> {
+        (self.role_lifetime != 0).then(|| now.saturating_add_unsigned(self.role_lifetime))
+    }
+}
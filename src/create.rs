@@ -3,7 +3,7 @@ use serenity::all::{
     GuildId, Permissions, ResolvedOption, ResolvedValue,
 };
 
-use crate::Handler;
+use crate::{Handler, expiry};
 
 //
 
@@ -57,14 +57,37 @@ pub async fn run(
     };
     let colour = colour & 0xFFFFFF;
 
+    let Ok(config) = handler
+        .guild_config(Some(guild_id))
+        .await
+        .inspect_err(|err| tracing::error!("failed to get guild settings: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    let member_roles = interaction
+        .member
+        .as_ref()
+        .map_or(&[][..], |member| &member.roles);
+    let Ok(max_owned) = handler
+        .max_owned_roles(guild_id, member_roles)
+        .await
+        .inspect_err(|err| tracing::error!("failed to get the owned role limit: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    let lock = handler.role_lock(guild_id);
+    let _guard = lock.lock().await;
+
     let new_role = match guild_id
         .create_role(
             &ctx.http,
             EditRole::new()
                 .name(*name)
                 .colour(colour)
-                .hoist(true)
-                .mentionable(true)
+                .hoist(config.hoist)
+                .mentionable(config.mentionable)
                 .permissions(Permissions::empty()),
         )
         .await
@@ -81,7 +104,16 @@ pub async fn run(
     };
 
     let Ok(success) = handler
-        .create_role(guild_id, new_role.id, name, interaction.user.id)
+        .db
+        .create_role(
+            guild_id,
+            new_role.id,
+            name,
+            interaction.user.id,
+            max_owned,
+            handler.config.role_expires_at(expiry::now()),
+            i64::from(config.role_cost),
+        )
         .await
         .inspect_err(|err| tracing::error!("failed to create role: {err}"))
     else {
@@ -91,7 +123,13 @@ pub async fn run(
 
     if !success {
         _ = guild_id.delete_role(&ctx.http, new_role.id).await;
-        return Err("too many owned roles".to_string());
+        if config.role_cost == 0 {
+            return Err("too many owned roles".to_string());
+        }
+        return Err(format!(
+            "too many owned roles or not enough currency, a new role costs {}",
+            config.role_cost
+        ));
     }
 
     Ok(format!("new role {name} created"))
@@ -33,6 +33,7 @@ pub async fn run(
     };
 
     let Ok(success) = handler
+        .db
         .delete_role(guild_id, role.id, interaction.user.id)
         .await
         .inspect_err(|err| tracing::error!("failed to delete role: {err}"))
@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Result;
+use serenity::all::{CreateAllowedMentions, CreateMessage, GuildId, Http, RoleId, UserId};
+use tokio::time;
+
+use crate::{Handler, expiry};
+
+//
+
+/// periodically orphan or delete the roles of owners
+/// who left more than `departure_grace_period` ago,
+/// and purge guilds the bot left more than `guild_retention` ago
+pub async fn run(handler: Arc<Handler>, http: Arc<Http>) {
+    let mut interval = time::interval(handler.config.maintenance_interval());
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = check(&handler, &http).await {
+            tracing::error!("failed to check departed owners: {err}");
+        }
+
+        if let Err(err) = purge(&handler).await {
+            tracing::error!("failed to purge left guilds: {err}");
+        }
+    }
+}
+
+async fn purge(handler: &Handler) -> Result<()> {
+    let before = expiry::now().saturating_sub_unsigned(handler.config.guild_retention);
+
+    for guild_id in handler.db.left_guilds(before).await? {
+        if handler.db.purge_guild(guild_id, before).await? {
+            tracing::info!("purged guild {guild_id}");
+            handler.guild_settings.remove(&guild_id);
+            handler.main_channels.remove(&guild_id);
+        }
+    }
+
+    Ok(())
+}
+
+async fn check(handler: &Handler, http: &Http) -> Result<()> {
+    let before = expiry::now().saturating_sub_unsigned(handler.config.departure_grace_period);
+
+    for (guild_id, user_id) in handler.db.departures(before).await? {
+        if let Err(err) = resolve(handler, http, guild_id, user_id, before).await {
+            tracing::error!("failed to resolve the departure of {user_id}: {err}");
+        }
+    }
+
+    Ok(())
+}
+
+async fn resolve(
+    handler: &Handler,
+    http: &Http,
+    guild_id: GuildId,
+    user_id: UserId,
+    before: i64,
+) -> Result<()> {
+    let config = handler.guild_config(Some(guild_id)).await?;
+
+    let content = if config.delete_departed_roles {
+        let roles = handler
+            .db
+            .delete_departed(guild_id, user_id, before)
+            .await?;
+        for role_id in &roles {
+            if let Err(err) = guild_id.delete_role(http, *role_id).await {
+                tracing::error!("failed to delete a departed owner's role: {err}");
+            }
+        }
+
+        if roles.is_empty() {
+            return Ok(());
+        }
+        format!(
+            "<@{user_id}> left, {} of their roles were deleted",
+            roles.len()
+        )
+    } else {
+        let roles = handler
+            .db
+            .orphan_departed(guild_id, user_id, before)
+            .await?;
+
+        if roles.is_empty() {
+            return Ok(());
+        }
+        format!(
+            "<@{user_id}> left, their roles are now orphans: {}",
+            mentions(&roles)
+        )
+    };
+
+    tracing::info!("resolved the departure of {user_id} in guild {guild_id}");
+
+    let Some(channel_id) = handler.main_channel(guild_id).await? else {
+        return Ok(());
+    };
+
+    let message = CreateMessage::new()
+        .content(content)
+        .allowed_mentions(CreateAllowedMentions::new());
+    channel_id.send_message(http, message).await?;
+
+    Ok(())
+}
+
+pub fn mentions(roles: &[RoleId]) -> String {
+    roles
+        .iter()
+        .map(|role_id| format!("<@&{role_id}>"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
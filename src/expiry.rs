@@ -0,0 +1,111 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+use serenity::all::{CreateAllowedMentions, CreateMessage, Http};
+use tokio::time;
+
+use crate::{Handler, config::ExpiryAction, store::ExpiringRole};
+
+//
+
+/// current unix timestamp
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64)
+}
+
+/// periodically warn the owners of expiring roles and expire the old ones
+pub async fn run(handler: Arc<Handler>, http: Arc<Http>) {
+    let mut interval = time::interval(handler.config.maintenance_interval());
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = check(&handler, &http).await {
+            tracing::error!("failed to check role expiry: {err}");
+        }
+    }
+}
+
+async fn check(handler: &Handler, http: &Http) -> Result<()> {
+    let now = now();
+
+    for role in handler.db.expired_roles(now).await? {
+        expire(handler, http, role, now).await;
+    }
+
+    let warn_before = now.saturating_add_unsigned(handler.config.expiry_warning);
+    for role in handler.db.expiring_roles(warn_before).await? {
+        warn(handler, http, role).await;
+    }
+
+    Ok(())
+}
+
+async fn expire(handler: &Handler, http: &Http, role: ExpiringRole, now: i64) {
+    let ExpiringRole {
+        guild_id, role_id, ..
+    } = role;
+
+    match handler.config.expiry_action {
+        ExpiryAction::Orphan => match handler.db.orphan_expired(guild_id, role_id, now).await {
+            Ok(true) => tracing::info!("role {role_id} in guild {guild_id} expired"),
+            Ok(false) => {}
+            Err(err) => tracing::error!("failed to orphan an expired role: {err}"),
+        },
+        ExpiryAction::Delete => {
+            match handler.db.delete_expired(guild_id, role_id, now).await {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(err) => {
+                    tracing::error!("failed to delete an expired role: {err}");
+                    return;
+                }
+            }
+
+            tracing::info!("role {role_id} in guild {guild_id} expired");
+            if let Err(err) = guild_id.delete_role(http, role_id).await {
+                tracing::error!("failed to delete an expired role: {err}");
+            }
+        }
+    }
+}
+
+async fn warn(handler: &Handler, http: &Http, role: ExpiringRole) {
+    let ExpiringRole {
+        guild_id,
+        role_id,
+        owner_user_id,
+        expires_at,
+    } = role;
+
+    let main_channel = match handler.main_channel(guild_id).await {
+        Ok(main_channel) => main_channel,
+        Err(err) => {
+            tracing::error!("failed to get the main channel: {err}");
+            return;
+        }
+    };
+
+    // no main channel, nowhere to warn
+    if let Some(channel_id) = main_channel {
+        let message = CreateMessage::new()
+            .content(format!(
+                "<@{owner_user_id}> your role <@&{role_id}> expires <t:{expires_at}:R>, \
+                use `/extend` to keep it"
+            ))
+            .allowed_mentions(CreateAllowedMentions::new().users([owner_user_id]));
+
+        if let Err(err) = channel_id.send_message(http, message).await {
+            tracing::error!("failed to warn about an expiring role: {err}");
+            return;
+        }
+    }
+
+    if let Err(err) = handler.db.set_expiry_warned(guild_id, role_id).await {
+        tracing::error!("failed to mark the expiry warning: {err}");
+    }
+}
@@ -0,0 +1,82 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, GuildId,
+    ResolvedOption, ResolvedValue,
+};
+
+use crate::{Handler, expiry};
+
+//
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("extend")
+        .description("Push the expiry of an owned role forward")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Role, "role", "role to be extended")
+                .required(true),
+        )
+}
+
+pub async fn run(
+    handler: &Handler,
+    _ctx: &Context,
+    interaction: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let mut options = interaction.data.options();
+
+    let Some(ResolvedOption {
+        value: ResolvedValue::Role(role),
+        ..
+    }) = options.pop()
+    else {
+        return Err("missing role".to_string());
+    };
+
+    if handler.config.role_lifetime == 0 {
+        return Err("roles do not expire".to_string());
+    }
+    let lifetime = i64::try_from(handler.config.role_lifetime).unwrap_or(i64::MAX);
+    let now = expiry::now();
+    // only once the owner has been warned, so extends can't pile up
+    let due_before = now.saturating_add_unsigned(handler.config.expiry_warning);
+
+    let Ok(config) = handler
+        .guild_config(Some(guild_id))
+        .await
+        .inspect_err(|err| tracing::error!("failed to get guild settings: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    let Ok(expires_at) = handler
+        .db
+        .extend_role(
+            guild_id,
+            role.id,
+            interaction.user.id,
+            now,
+            due_before,
+            lifetime,
+            i64::from(config.extend_cost),
+        )
+        .await
+        .inspect_err(|err| tracing::error!("failed to extend role: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    let Some(expires_at) = expires_at else {
+        let reasons = format!(
+            "role not owned, it never expires or it doesn't expire before <t:{due_before}:R>"
+        );
+        if config.extend_cost == 0 {
+            return Err(reasons);
+        }
+        return Err(format!(
+            "{reasons} or not enough currency, extending costs {}",
+            config.extend_cost
+        ));
+    };
+
+    Ok(format!("role {} now expires <t:{expires_at}:R>", role.name))
+}
@@ -0,0 +1,202 @@
+use serenity::all::{
+    ButtonStyle, CommandInteraction, CommandOptionType, ComponentInteraction, Context,
+    CreateActionRow, CreateAllowedMentions, CreateButton, CreateCommand, CreateCommandOption,
+    CreateMessage, GuildId, ResolvedOption, ResolvedValue, RoleId, UserId,
+};
+
+use crate::{DAY_SECONDS, Handler, expiry, store::QueryRoleResult};
+
+//
+
+/// seconds an offer can be accepted for
+const OFFER_LIFETIME: i64 = DAY_SECONDS as i64;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("give")
+        .description("Give an owned role to another user")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Role, "role", "role to be given")
+                .required(true),
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::User, "user", "new owner").required(true),
+        )
+}
+
+pub async fn run(
+    handler: &Handler,
+    ctx: &Context,
+    interaction: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let options = interaction.data.options();
+
+    let Some(ResolvedOption {
+        value: ResolvedValue::Role(role),
+        ..
+    }) = options.first()
+    else {
+        return Err("missing role".to_string());
+    };
+
+    let Some(ResolvedOption {
+        value: ResolvedValue::User(user, _),
+        ..
+    }) = options.get(1)
+    else {
+        return Err("missing target user".to_string());
+    };
+
+    if user.id == interaction.user.id || user.bot {
+        return Err("invalid target user".to_string());
+    }
+
+    match handler.db.query_role(guild_id, role.id).await {
+        Ok(QueryRoleResult::Owned(owner)) if owner == interaction.user.id => {}
+        Ok(_) => return Err("role not owned".to_string()),
+        Err(err) => {
+            tracing::error!("failed to query role: {err}");
+            return Err("internal error".to_string());
+        }
+    }
+
+    let offer = Offer {
+        role_id: role.id,
+        from_user_id: interaction.user.id,
+        to_user_id: user.id,
+        offered_at: expiry::now(),
+    };
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(offer.custom_id("accept"))
+            .label("Accept")
+            .style(ButtonStyle::Success),
+        CreateButton::new(offer.custom_id("decline"))
+            .label("Decline")
+            .style(ButtonStyle::Danger),
+    ]);
+    let message = CreateMessage::new()
+        .content(format!(
+            "<@{}>, <@{}> wants to give you <@&{}>",
+            user.id, interaction.user.id, role.id
+        ))
+        .allowed_mentions(CreateAllowedMentions::new().users([user.id]))
+        .components(vec![buttons]);
+
+    if let Err(err) = interaction
+        .channel_id
+        .send_message(&ctx.http, message)
+        .await
+    {
+        tracing::error!("failed to send the offer: {err}");
+        return Err("internal error".to_string());
+    }
+
+    Ok(format!("offered role {} to <@{}>", role.name, user.id))
+}
+
+/// handle the accept and decline buttons of an offer,
+/// the result replaces the offer message
+pub async fn component(
+    handler: &Handler,
+    interaction: &ComponentInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let Some((action, offer)) = Offer::parse(&interaction.data.custom_id) else {
+        return Err("invalid button".to_string());
+    };
+
+    let user_id = interaction.user.id;
+    match action {
+        // either side can call it off
+        "decline" if user_id == offer.to_user_id => {
+            return Ok(format!("<@{user_id}> declined <@&{}>", offer.role_id));
+        }
+        "decline" if user_id == offer.from_user_id => {
+            return Ok(format!(
+                "<@{user_id}> withdrew the offer of <@&{}>",
+                offer.role_id
+            ));
+        }
+        "accept" if user_id == offer.to_user_id => {}
+        "accept" | "decline" => return Err("this offer is not for you".to_string()),
+        _ => return Err("invalid button".to_string()),
+    }
+
+    if expiry::now().saturating_sub(offer.offered_at) > OFFER_LIFETIME {
+        return Ok(format!(
+            "the offer of <@&{}> to <@{user_id}> expired",
+            offer.role_id
+        ));
+    }
+
+    let member_roles = interaction
+        .member
+        .as_ref()
+        .map_or(&[][..], |member| &member.roles);
+    let Ok(max_owned) = handler
+        .max_owned_roles(guild_id, member_roles)
+        .await
+        .inspect_err(|err| tracing::error!("failed to get the owned role limit: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    let Ok(success) = handler
+        .db
+        .give_role(
+            guild_id,
+            offer.role_id,
+            offer.from_user_id,
+            offer.to_user_id,
+            max_owned,
+        )
+        .await
+        .inspect_err(|err| tracing::error!("failed to give role: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    if !success {
+        return Err(
+            "the role is no longer owned by the giver or you own too many roles".to_string(),
+        );
+    }
+
+    Ok(format!(
+        "role <@&{}> ownership moved from <@{}> to <@{}>",
+        offer.role_id, offer.from_user_id, offer.to_user_id
+    ))
+}
+
+/// everything needed to accept an offer, stored in the button custom id
+struct Offer {
+    role_id: RoleId,
+    from_user_id: UserId,
+    to_user_id: UserId,
+    /// unix timestamp
+    offered_at: i64,
+}
+
+impl Offer {
+    fn custom_id(&self, action: &str) -> String {
+        format!(
+            "give:{action}:{}:{}:{}:{}",
+            self.role_id, self.from_user_id, self.to_user_id, self.offered_at
+        )
+    }
+
+    fn parse(custom_id: &str) -> Option<(&str, Self)> {
+        let mut parts = custom_id.strip_prefix("give:")?.split(':');
+        let action = parts.next()?;
+        let mut id = || parts.next()?.parse::<u64>().ok().filter(|id| *id != 0);
+
+        let offer = Self {
+            role_id: RoleId::new(id()?),
+            from_user_id: UserId::new(id()?),
+            to_user_id: UserId::new(id()?),
+            // offers from before they had a timestamp count as expired
+            offered_at: parts.next().and_then(|at| at.parse().ok()).unwrap_or(0),
+        };
+        Some((action, offer))
+    }
+}
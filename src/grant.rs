@@ -0,0 +1,95 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, GuildId,
+    Permissions, ResolvedOption, ResolvedValue,
+};
+
+use crate::{Handler, store::LedgerReason};
+
+//
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("grant")
+        .description("Grant or take away role currency")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::User, "user", "target user").required(true),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "amount",
+                "amount to grant, negative takes it away",
+            )
+            .required(true),
+        )
+}
+
+pub async fn run(
+    handler: &Handler,
+    _ctx: &Context,
+    interaction: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let Some(member) = interaction.member.as_deref() else {
+        return Err("not in a guild".to_string());
+    };
+
+    let Some(permissions) = member.permissions else {
+        tracing::error!("member.permissions should always be Some in commands");
+        return Err("internal error".to_string());
+    };
+
+    if !permissions.administrator() {
+        return Err("permission denied".to_string());
+    }
+
+    let options = interaction.data.options();
+
+    let Some(ResolvedOption {
+        value: ResolvedValue::User(user, _),
+        ..
+    }) = options.first()
+    else {
+        return Err("missing target user".to_string());
+    };
+
+    let Some(ResolvedOption {
+        value: ResolvedValue::Integer(amount),
+        ..
+    }) = options.get(1)
+    else {
+        return Err("missing amount".to_string());
+    };
+
+    let reason = LedgerReason::Grant {
+        admin_user_id: interaction.user.id,
+    };
+
+    if *amount >= 0 {
+        if let Err(err) = handler.db.deposit(guild_id, user.id, *amount, reason).await {
+            tracing::error!("failed to deposit: {err}");
+            return Err("internal error".to_string());
+        }
+
+        return Ok(format!("granted {amount} to <@{}>", user.id));
+    }
+
+    let Some(amount) = amount.checked_neg() else {
+        return Err("invalid amount".to_string());
+    };
+
+    let Ok(success) = handler
+        .db
+        .withdraw(guild_id, user.id, amount, reason)
+        .await
+        .inspect_err(|err| tracing::error!("failed to withdraw: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    if !success {
+        return Err("not enough currency".to_string());
+    }
+
+    Ok(format!("took {amount} from <@{}>", user.id))
+}
@@ -0,0 +1,119 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, GuildId,
+    ResolvedValue,
+};
+
+use crate::{
+    Handler,
+    store::{LedgerEntry, LedgerReason},
+};
+
+//
+
+const PAGE_SIZE: usize = 10;
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("ledger")
+        .description("Show the role currency history of a user")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::User,
+            "user",
+            "target user, defaults to yourself, moderators only",
+        ))
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "page", "page number")
+                .min_int_value(1),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "rewards",
+            "include message rewards, defaults to false",
+        ))
+}
+
+pub async fn run(
+    handler: &Handler,
+    _ctx: &Context,
+    interaction: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let mut user_id = interaction.user.id;
+    let mut page = 1;
+    let mut rewards = false;
+    for option in interaction.data.options() {
+        match (option.name, option.value) {
+            ("user", ResolvedValue::User(user, _)) => user_id = user.id,
+            ("page", ResolvedValue::Integer(n)) => page = n,
+            ("rewards", ResolvedValue::Boolean(b)) => rewards = b,
+            _ => {}
+        }
+    }
+
+    if user_id != interaction.user.id {
+        let Some(member) = interaction.member.as_deref() else {
+            return Err("not in a guild".to_string());
+        };
+
+        let Some(permissions) = member.permissions else {
+            tracing::error!("member.permissions should always be Some in commands");
+            return Err("internal error".to_string());
+        };
+
+        if !permissions.administrator() && !permissions.moderate_members() {
+            return Err("permission denied".to_string());
+        }
+    }
+
+    let Ok(count) = handler
+        .db
+        .ledger_count(guild_id, user_id, rewards)
+        .await
+        .inspect_err(|err| tracing::error!("failed to get ledger count: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    if count == 0 {
+        return Ok(format!("<@{user_id}> has no transactions"));
+    }
+
+    let pages = count.div_ceil(PAGE_SIZE);
+    let page = usize::try_from(page).unwrap_or(1).clamp(1, pages);
+
+    let Ok(entries) = handler
+        .db
+        .ledger(
+            guild_id,
+            user_id,
+            rewards,
+            (page - 1) * PAGE_SIZE,
+            PAGE_SIZE,
+        )
+        .await
+        .inspect_err(|err| tracing::error!("failed to get ledger: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    let mut result = format!("<@{user_id}> transactions, page {page}/{pages}:\n");
+    for entry in entries {
+        result.push_str(&describe(&entry));
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+fn describe(entry: &LedgerEntry) -> String {
+    let what = match entry.reason {
+        LedgerReason::MessageReward => "message reward".to_string(),
+        LedgerReason::Grant { admin_user_id } => format!("granted by <@{admin_user_id}>"),
+        LedgerReason::Transfer { other_user_id } if entry.amount < 0 => {
+            format!("transfer to <@{other_user_id}>")
+        }
+        LedgerReason::Transfer { other_user_id } => format!("transfer from <@{other_user_id}>"),
+        LedgerReason::CreateRole { role_id } => format!("created <@&{role_id}>"),
+        LedgerReason::ExtendRole { role_id } => format!("extended <@&{role_id}>"),
+    };
+
+    format!(" - <t:{}:f> {:+} {what}", entry.created_at, entry.amount)
+}
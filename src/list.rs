@@ -1,6 +1,6 @@
 use serenity::all::{
     CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption,
-    CreateMessage, GuildId, ResolvedValue, UserId,
+    CreateMessage, GuildId, ResolvedValue, RoleId, UserId,
 };
 
 use crate::Handler;
@@ -35,30 +35,40 @@ pub async fn run(
     ];
 
     let user_id: UserId;
+    let member_roles: &[RoleId];
     let just_count: bool;
 
+    let own_roles = interaction
+        .member
+        .as_ref()
+        .map_or(&[][..], |member| &member.roles);
+
     match options {
         [
-            Some(ResolvedValue::User(user, _)),
+            Some(ResolvedValue::User(user, member)),
             Some(ResolvedValue::Boolean(count)),
         ]
         | [
             Some(ResolvedValue::Boolean(count)),
-            Some(ResolvedValue::User(user, _)),
+            Some(ResolvedValue::User(user, member)),
         ] => {
             user_id = user.id;
+            member_roles = member.map_or(&[][..], |member| &member.roles);
             just_count = count;
         }
         [Some(ResolvedValue::Boolean(count)), None] => {
             user_id = interaction.user.id;
+            member_roles = own_roles;
             just_count = count;
         }
-        [Some(ResolvedValue::User(user, _)), None] => {
+        [Some(ResolvedValue::User(user, member)), None] => {
             user_id = user.id;
+            member_roles = member.map_or(&[][..], |member| &member.roles);
             just_count = false;
         }
         [None, None] => {
             user_id = interaction.user.id;
+            member_roles = own_roles;
             just_count = false;
         }
         _ => {
@@ -68,6 +78,7 @@ pub async fn run(
 
     if just_count {
         let Ok(list) = handler
+            .db
             .list_count(guild_id, user_id)
             .await
             .inspect_err(|err| tracing::error!("failed to get a count of owned roles: {err}"))
@@ -75,9 +86,22 @@ pub async fn run(
             return Err("internal error".to_string());
         };
 
-        Ok(format!("<@{user_id}> owns {list} roles"))
+        let Ok(max_owned) = handler
+            .max_owned_roles(guild_id, member_roles)
+            .await
+            .inspect_err(|err| tracing::error!("failed to get the owned role limit: {err}"))
+        else {
+            return Err("internal error".to_string());
+        };
+
+        if let Some(max_owned) = max_owned {
+            Ok(format!("<@{user_id}> owns {list} of {max_owned} roles"))
+        } else {
+            Ok(format!("<@{user_id}> owns {list} roles, no limit"))
+        }
     } else {
         let Ok(list) = handler
+            .db
             .list(guild_id, user_id)
             .await
             .inspect_err(|err| tracing::error!("failed to get a list of owned roles: {err}"))
@@ -91,7 +115,7 @@ pub async fn run(
 
         let mut buf = String::new();
 
-        for (role,) in list {
+        for role in list {
             use std::fmt::Write;
             let len = buf.len();
             _ = writeln!(&mut buf, " - {role}");
@@ -1,37 +1,61 @@
 use std::{
     env,
-    fmt::Display,
     sync::{Arc, Weak},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use color_eyre::eyre::Result;
-use futures::{StreamExt, stream::FuturesUnordered};
+use dashmap::DashMap;
 use serenity::{
     Client,
     all::{
-        ChannelId, Command, Context, CreateInteractionResponse, CreateInteractionResponseMessage,
-        CreateMessage, EventHandler, GatewayIntents, GuildId, Interaction, Member, Message,
-        MessageId, MessageUpdateEvent, Permissions, Ready, RoleId, Settings, UserId,
+        ChannelId, Command, ComponentInteraction, Context, CreateAllowedMentions,
+        CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, EventHandler,
+        Guild, GuildId, GuildMemberUpdateEvent, Http, Interaction, Member, Message, MessageId,
+        MessageUpdateEvent, Permissions, Ready, Role, RoleId, Settings, UnavailableGuild, User,
+        UserId,
     },
     async_trait,
 };
-use sqlx::{PgPool, postgres::PgPoolOptions};
 use tokio::{signal, sync::Mutex, time};
 
+use crate::{
+    config::{Config, GuildConfig, RestoreRoles},
+    store::{GuildSettings, LedgerReason, RoleStore},
+};
+
 //
 
 mod take_ownership;
 
+mod abandon;
 mod create;
 mod delete;
+mod extend;
+mod give;
 mod list;
 mod orphaned;
+mod sticky;
 
 mod add;
 mod query;
 mod remove;
 
+mod balance;
+mod grant;
+mod ledger;
+mod transfer;
+
+mod main_channel;
+mod settings;
+mod sync_status;
+
+mod config;
+mod departure;
+mod expiry;
+mod store;
+mod sync;
+
 //
 
 pub const HOUR_SECONDS: u64 = 60 * 60;
@@ -40,482 +64,493 @@ pub const WEEK_SECONDS: u64 = DAY_SECONDS * 7;
 
 //
 
-pub enum QueryRoleResult {
-    Owned(UserId),
-    Orphan,
-    NotFound,
-}
-
 pub struct Handler {
     me: Weak<Handler>,
-    db: PgPool,
+    db: Box<dyn RoleStore>,
+    config: Config,
+
+    /// cached [`RoleStore::guild_settings`]
+    guild_settings: DashMap<GuildId, GuildSettings>,
+    /// cached [`RoleStore::get_main_channel`]
+    main_channels: DashMap<GuildId, Option<ChannelId>>,
+    /// see [`sync::update_database`]
+    sync_status: DashMap<GuildId, sync::Status>,
+    /// see [`Handler::role_lock`]
+    role_locks: DashMap<GuildId, Arc<Mutex<()>>>,
+
+    /// when each user last earned the message reward
+    last_reward: DashMap<(GuildId, UserId), Instant>,
 
     last_u: Mutex<Option<UserId>>,
 }
 
 impl Handler {
-    /// returns true on success
-    pub async fn create_guild(&self, guild_id: GuildId) -> Result<bool> {
-        let rows = sqlx::query(
-            "
-INSERT INTO guilds (guild_id)
-VALUES ($1)
-ON CONFLICT DO NOTHING
-            ",
-        )
-        .bind(guild_id.get() as i64)
-        .execute(&self.db)
-        .await?;
-
-        tracing::debug!("add_guild rows affected: {}", rows.rows_affected());
-        Ok(rows.rows_affected() == 1)
-    }
+    /// the config of a guild with its `/settings` applied,
+    /// `None` gives the plain config for direct messages
+    pub async fn guild_config(&self, guild_id: Option<GuildId>) -> Result<GuildConfig> {
+        let Some(guild_id) = guild_id else {
+            return Ok(self.config.resolve(&GuildSettings::default()));
+        };
 
-    /// returns true on success
-    pub async fn create_role_force(
-        &self,
-        guild_id: GuildId,
-        role_id: RoleId,
-        name: &str,
-        owner_user_id: Option<UserId>,
-    ) -> Result<bool> {
-        let rows = sqlx::query(
-            "
-INSERT INTO roles (role_id, guild_id, name, owner_user_id)
-VALUES ($1, $2, $3, $4)
-ON CONFLICT DO NOTHING
-        ",
-        )
-        .bind(role_id.get() as i64)
-        .bind(guild_id.get() as i64)
-        .bind(name)
-        .bind(owner_user_id.map(|id| id.get() as i64))
-        .execute(&self.db)
-        .await?;
+        if let Some(settings) = self.guild_settings.get(&guild_id) {
+            return Ok(self.config.resolve(&settings));
+        }
 
-        tracing::debug!("add_role rows affected: {}", rows.rows_affected());
-        Ok(rows.rows_affected() == 1)
+        let settings = self.db.guild_settings(guild_id).await?;
+        let config = self.config.resolve(&settings);
+        self.guild_settings.insert(guild_id, settings);
+        Ok(config)
     }
 
-    /// returns true on success
-    pub async fn create_role(
+    /// how many roles a member with `roles` can own, `None` is unlimited
+    ///
+    /// per role limits can only raise the guild limit
+    pub async fn max_owned_roles(
         &self,
         guild_id: GuildId,
-        role_id: RoleId,
-        name: &str,
-        owner_user_id: UserId,
-    ) -> Result<bool> {
-        let rows = sqlx::query(
-            "
-INSERT INTO roles (role_id, guild_id, name, owner_user_id)
-SELECT $1, $2, $3, $4
-WHERE (
-    SELECT COUNT(*)
-    FROM roles
-    WHERE owner_user_id = $4
-      AND guild_id = $2
-) < 20
-ON CONFLICT DO NOTHING
-        ",
-        )
-        .bind(role_id.get() as i64)
-        .bind(guild_id.get() as i64)
-        .bind(name)
-        .bind(owner_user_id.get() as i64)
-        .execute(&self.db)
-        .await?;
+        roles: &[RoleId],
+    ) -> Result<Option<u32>> {
+        let config = self.guild_config(Some(guild_id)).await?;
+        let role_limits = self.db.role_limits(guild_id).await?;
+
+        // 0 is unlimited
+        let mut max = config.max_owned_roles;
+        for (role_id, limit) in role_limits {
+            if !roles.contains(&role_id) {
+                continue;
+            }
 
-        tracing::debug!("add_role rows affected: {}", rows.rows_affected());
-        Ok(rows.rows_affected() == 1)
+            max = if max == 0 || limit == 0 {
+                0
+            } else {
+                max.max(limit)
+            };
+        }
+
+        Ok((max != 0).then_some(max))
     }
 
-    /// returns true on success
-    pub async fn delete_role(
+    pub async fn set_guild_settings(
         &self,
         guild_id: GuildId,
-        role_id: RoleId,
-        user_id: UserId,
-    ) -> Result<bool> {
-        let rows = sqlx::query(
-            "
-DELETE FROM roles
-WHERE guild_id = $1
-  AND role_id = $2
-  AND owner_user_id = $3
-RETURNING *
-        ",
-        )
-        .bind(guild_id.get() as i64)
-        .bind(role_id.get() as i64)
-        .bind(user_id.get() as i64)
-        .execute(&self.db)
-        .await?;
-
-        tracing::debug!("delete_role rows affected: {}", rows.rows_affected());
-        Ok(rows.rows_affected() != 0)
+        settings: GuildSettings,
+    ) -> Result<()> {
+        self.db.set_guild_settings(guild_id, &settings).await?;
+        self.guild_settings.insert(guild_id, settings);
+        Ok(())
     }
 
-    pub async fn list_count(&self, guild_id: GuildId, user_id: UserId) -> Result<usize> {
-        let (rows,) = sqlx::query_as::<_, (i64,)>(
-            "
-SELECT COUNT(*)
-FROM roles
-WHERE guild_id = $1
-  AND owner_user_id = $2
-        ",
-        )
-        .bind(guild_id.get() as i64)
-        .bind(user_id.get() as i64)
-        .fetch_one(&self.db)
-        .await?;
+    /// the channel for bot announcements, if the guild has set one
+    pub async fn main_channel(&self, guild_id: GuildId) -> Result<Option<ChannelId>> {
+        if let Some(channel_id) = self.main_channels.get(&guild_id) {
+            return Ok(*channel_id);
+        }
 
-        Ok(rows as usize)
+        let channel_id = self.db.get_main_channel(guild_id).await?;
+        self.main_channels.insert(guild_id, channel_id);
+        Ok(channel_id)
     }
 
-    pub async fn list(&self, guild_id: GuildId, user_id: UserId) -> Result<Vec<(String,)>> {
-        let rows = sqlx::query_as(
-            "
-SELECT name
-FROM roles
-WHERE guild_id = $1
-  AND owner_user_id = $2
-        ",
-        )
-        .bind(guild_id.get() as i64)
-        .bind(user_id.get() as i64)
-        .fetch_all(&self.db)
-        .await?;
+    pub async fn set_main_channel(&self, guild_id: GuildId, channel_id: ChannelId) -> Result<()> {
+        self.db.set_main_channel(guild_id, channel_id).await?;
+        self.main_channels.insert(guild_id, Some(channel_id));
+        Ok(())
+    }
 
-        Ok(rows)
+    /// held while `/create` creates a role and stores its owner,
+    /// so `guild_role_create` does not store it as an unowned role first
+    pub fn role_lock(&self, guild_id: GuildId) -> Arc<Mutex<()>> {
+        self.role_locks.entry(guild_id).or_default().clone()
     }
 
-    pub async fn orphaned_count(&self, guild_id: GuildId) -> Result<usize> {
-        let (rows,) = sqlx::query_as::<_, (i64,)>(
-            "
-SELECT COUNT(*)
-FROM roles
-WHERE guild_id = $1
-  AND owner_user_id IS NULL
-        ",
-        )
-        .bind(guild_id.get() as i64)
-        .fetch_one(&self.db)
-        .await?;
+    /// keep the `roles` row of a discord role in sync,
+    /// roles with permissions are not managed
+    async fn sync_role(&self, role: &Role) {
+        if role.permissions != Permissions::empty() {
+            match self.db.forget_role(role.guild_id, role.id).await {
+                Ok(true) => {
+                    tracing::info!("role {} got permissions and is no longer managed", role.id)
+                }
+                Ok(false) => {}
+                Err(err) => tracing::error!("failed to forget a role: {err}"),
+            }
+            return;
+        }
 
-        Ok(rows as usize)
+        if let Err(err) = self
+            .db
+            .upsert_role(role.guild_id, role.id, &role.name)
+            .await
+        {
+            tracing::error!("failed to sync a role: {err}");
+        }
     }
 
-    pub async fn orphaned(&self, guild_id: GuildId) -> Result<Vec<(String,)>> {
-        let rows = sqlx::query_as(
-            "
-SELECT name
-FROM roles
-WHERE guild_id = $1
-  AND owner_user_id IS NULL
-        ",
-        )
-        .bind(guild_id.get() as i64)
-        .fetch_all(&self.db)
-        .await?;
+    /// give a rejoining member their roles back as far as `restore_roles` allows,
+    /// then tell the main channel how it went
+    async fn restore_roles(&self, http: &Http, guild_id: GuildId, user_id: UserId) -> Result<()> {
+        let roles = match self.guild_config(Some(guild_id)).await?.restore_roles {
+            RestoreRoles::On => self.db.restored_roles(guild_id, user_id, false).await?,
+            RestoreRoles::Sticky => self.db.restored_roles(guild_id, user_id, true).await?,
+            RestoreRoles::Off => return Ok(()),
+        };
 
-        Ok(rows)
-    }
+        if roles.is_empty() {
+            return Ok(());
+        }
 
-    /// returns true on success
-    pub async fn create_user(&self, guild_id: GuildId, user_id: UserId) -> Result<bool> {
-        let rows = sqlx::query(
-            "
-INSERT INTO users (user_id, guild_id)
-VALUES ($1, $2)
-ON CONFLICT DO NOTHING
-        ",
-        )
-        .bind(user_id.get() as i64)
-        .bind(guild_id.get() as i64)
-        .execute(&self.db)
-        .await?;
+        let mut restored = Vec::new();
+        let mut failed = Vec::new();
+        for role_id in roles {
+            match http
+                .add_member_role(
+                    guild_id,
+                    user_id,
+                    role_id,
+                    Some("prevented rejoin role removal"),
+                )
+                .await
+            {
+                Ok(()) => restored.push(role_id),
+                Err(err) => {
+                    tracing::debug!("failed to restore role {role_id}: {err}");
+                    failed.push(role_id);
+                }
+            }
+        }
 
-        tracing::debug!("add_user rows affected: {}", rows.rows_affected());
-        Ok(rows.rows_affected() == 1)
-    }
+        let Some(channel_id) = self.main_channel(guild_id).await? else {
+            return Ok(());
+        };
 
-    /// returns true on success
-    pub async fn take_ownership(
-        &self,
-        guild_id: GuildId,
-        role_id: RoleId,
-        user_id: UserId,
-    ) -> Result<bool> {
-        let rows = sqlx::query(
-            "
-UPDATE roles
-SET owner_user_id = $3
-WHERE role_id = $1
-  AND guild_id = $2
-  AND owner_user_id IS NULL
-  AND (
-    SELECT COUNT(*)
-    FROM roles
-    WHERE owner_user_id = $3
-      AND guild_id = $2
-) < 20
-        ",
-        )
-        .bind(role_id.get() as i64)
-        .bind(guild_id.get() as i64)
-        .bind(user_id.get() as i64)
-        .execute(&self.db)
-        .await?;
+        let mut content = format!("<@{user_id}> rejoined");
+        if !restored.is_empty() {
+            content += &format!(", restored: {}", departure::mentions(&restored));
+        }
+        if !failed.is_empty() {
+            content += &format!(", failed to restore: {}", departure::mentions(&failed));
+        }
 
-        tracing::debug!("take_role rows affected: {}", rows.rows_affected());
-        Ok(rows.rows_affected() == 1)
+        let message = CreateMessage::new()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new());
+        channel_id.send_message(http, message).await?;
+
+        Ok(())
     }
 
-    pub async fn query_role(&self, guild_id: GuildId, role_id: RoleId) -> Result<QueryRoleResult> {
-        let result: Option<(Option<i64>,)> = sqlx::query_as(
-            "
-SELECT owner_user_id
-FROM roles
-WHERE guild_id = $1
-  AND role_id = $2
-    ",
-        )
-        .bind(guild_id.get() as i64)
-        .bind(role_id.get() as i64)
-        .fetch_optional(&self.db)
-        .await?;
+    /// the main channel of the guild, or `fallback` if it has none
+    async fn announcement_channel(
+        &self,
+        guild_id: Option<GuildId>,
+        fallback: ChannelId,
+    ) -> ChannelId {
+        let Some(guild_id) = guild_id else {
+            return fallback;
+        };
 
-        match result {
-            Some((Some(user_id),)) => Ok(QueryRoleResult::Owned(UserId::new(user_id as u64))),
-            Some((None,)) => Ok(QueryRoleResult::Orphan),
-            None => Ok(QueryRoleResult::NotFound),
+        match self.main_channel(guild_id).await {
+            Ok(channel_id) => channel_id.unwrap_or(fallback),
+            Err(err) => {
+                tracing::error!("failed to get the main channel: {err}");
+                fallback
+            }
         }
     }
 
-    /// returns true on success
-    pub async fn add_role(
-        &self,
-        guild_id: GuildId,
-        role_id: RoleId,
-        user_id: UserId,
-    ) -> Result<bool> {
-        let rows = sqlx::query(
-            "
-INSERT INTO user_roles (user_id, role_id, guild_id)
-VALUES ($1, $2, $3)
-ON CONFLICT DO NOTHING
-        ",
-        )
-        .bind(user_id.get() as i64)
-        .bind(role_id.get() as i64)
-        .bind(guild_id.get() as i64)
-        .execute(&self.db)
-        .await?;
+    /// buttons of earlier command replies,
+    /// success replaces the message and errors are only shown to the user
+    async fn component_interaction(&self, ctx: &Context, component: &ComponentInteraction) {
+        let Some(guild_id) = component.guild_id else {
+            return;
+        };
 
-        tracing::debug!("apply_role rows affected: {}", rows.rows_affected());
-        Ok(rows.rows_affected() == 1)
-    }
+        tracing::debug!(
+            "received component: {} from {}",
+            component.data.custom_id,
+            component.user.name
+        );
 
-    /// returns true on success
-    pub async fn remove_role(
-        &self,
-        guild_id: GuildId,
-        role_id: RoleId,
-        user_id: UserId,
-        caller_user_id: UserId,
-    ) -> Result<bool> {
-        // cant remove roles from self, except if it is owned
-        // => can remove if it is owned, or not self
-        let rows = if user_id == caller_user_id {
-            sqlx::query(
-                "
-DELETE FROM user_roles
-WHERE guild_id = $1
-  AND role_id = $2
-  AND user_id = $3
-  AND $4 = (
-      SELECT owner_user_id
-      FROM roles
-      WHERE guild_id = $1
-        AND role_id = $2
-  )
-RETURNING *
-            ",
-            )
-            .bind(guild_id.get() as i64)
-            .bind(role_id.get() as i64)
-            .bind(user_id.get() as i64)
-            .bind(caller_user_id.get() as i64)
-            .execute(&self.db)
-            .await?
-        } else {
-            sqlx::query(
-                "
-DELETE FROM user_roles
-WHERE guild_id = $1
-  AND role_id = $2
-  AND user_id = $3
-RETURNING *
-                ",
-            )
-            .bind(guild_id.get() as i64)
-            .bind(role_id.get() as i64)
-            .bind(user_id.get() as i64)
-            .execute(&self.db)
-            .await?
+        let result = match component.data.custom_id.split(':').next() {
+            Some("give") => give::component(self, component, guild_id).await,
+            _ => Err("???".to_string()),
         };
 
-        tracing::debug!("remove_role rows affected: {}", rows.rows_affected());
-        Ok(rows.rows_affected() != 0)
+        let response = match result {
+            Ok(content) => CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(Vec::new()),
+            ),
+            Err(content) => CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        };
+        if let Err(err) = component.create_response(&ctx.http, response).await {
+            tracing::error!("failed to respond to a component: {err}");
+        }
     }
 
-    pub async fn update_database(&self, ctx: &Context, guild_id: GuildId) -> Result<()> {
-        self.create_guild(guild_id).await?;
+    /// give the author of a guild message the message reward,
+    /// at most once per `message_reward_cooldown`
+    async fn reward_message(&self, message: &Message) {
+        let Some(guild_id) = message.guild_id else {
+            return;
+        };
+        if message.author.bot {
+            return;
+        }
 
-        let roles = guild_id.roles(&ctx.http).await?;
+        let key = (guild_id, message.author.id);
+        let cooldown = self.config.message_reward_cooldown();
+        if self
+            .last_reward
+            .get(&key)
+            .is_some_and(|last| last.elapsed() < cooldown)
+        {
+            return;
+        }
 
-        // add all new roles
-        let mut add_role_jobs = FuturesUnordered::new();
-        for (role_id, role) in roles.iter() {
-            if role.permissions != Permissions::empty() {
-                continue;
+        let reward = match self.guild_config(Some(guild_id)).await {
+            Ok(config) => config.message_reward,
+            Err(err) => {
+                tracing::error!("failed to get guild config: {err}");
+                return;
             }
-
-            add_role_jobs.push(async move {
-                //
-                self.create_role_force(guild_id, *role_id, &role.name, None)
-                    .await
-            });
+        };
+        if reward == 0 {
+            return;
         }
-        while let Some(next) = add_role_jobs.next().await {
-            if let Err(err) = next {
-                tracing::error!("update_database add_role error: {err}");
-            }
+        self.last_reward.insert(key, Instant::now());
+
+        if let Err(err) = self
+            .db
+            .deposit(
+                guild_id,
+                message.author.id,
+                i64::from(reward),
+                LedgerReason::MessageReward,
+            )
+            .await
+        {
+            tracing::error!("failed to reward a message: {err}");
         }
+    }
 
-        // add all new users, then add all new user role connections
-        let mut add_user_jobs = FuturesUnordered::new();
-        let mut add_user_roles_jobs = FuturesUnordered::new();
+    // pub async fn orphaned_roles(&self, ctx: Context, guild_id: GuildId) {
+    // }
+}
 
-        let mut members = guild_id.members_iter(&ctx.http).boxed();
-        while let Some(member) = members.next().await {
-            let member = match member {
-                Ok(member) => member,
-                Err(err) => {
-                    tracing::debug!("invalid member: {err}");
-                    continue;
-                }
-            };
+#[async_trait]
+impl EventHandler for Handler {
+    async fn guild_member_addition(&self, ctx: Context, new_member: Member) {
+        match self
+            .db
+            .cancel_departure(new_member.guild_id, new_member.user.id)
+            .await
+        {
+            Ok(true) => tracing::info!("role owner {} came back", new_member.user.id),
+            Ok(false) => {}
+            Err(err) => tracing::error!("failed to cancel a departure: {err}"),
+        }
 
-            add_user_jobs.push(async move {
-                //
-                self.create_user(guild_id, member.user.id).await
-            });
+        if let Err(err) = self
+            .restore_roles(&ctx.http, new_member.guild_id, new_member.user.id)
+            .await
+        {
+            tracing::error!("failed to restore roles: {err}");
+        }
+    }
 
-            for role_id in member.roles {
-                let Some(role) = roles.get(&role_id) else {
-                    continue;
-                };
-                if role.permissions != Permissions::empty() {
-                    continue;
-                }
+    async fn guild_create(&self, ctx: Context, guild: Guild, is_new: Option<bool>) {
+        if let Err(err) = self.db.set_guild_left(guild.id, None).await {
+            tracing::error!("failed to unmark a left guild: {err}");
+        }
 
-                add_user_roles_jobs.push(async move {
-                    //
-                    self.add_role(guild_id, role_id, member.user.id).await
-                });
-            }
+        // the guilds from before the start are synced in `ready`
+        if is_new != Some(true) {
+            return;
         }
-        while let Some(next) = add_user_jobs.next().await {
-            if let Err(err) = next {
-                tracing::error!("update_database add_user error: {err}");
+
+        tracing::info!("joined guild_id={}", guild.id);
+
+        let handler = self.me.upgrade().unwrap();
+        tokio::spawn(async move {
+            match sync::update_database(&handler, &ctx.http, guild.id).await {
+                Ok(report) => tracing::info!("synced guild_id={}: {report}", guild.id),
+                Err(err) => tracing::error!("failed to update guild_id={}: {err}", guild.id),
             }
+        });
+    }
+
+    async fn guild_delete(
+        &self,
+        _ctx: Context,
+        incomplete: UnavailableGuild,
+        _full: Option<Guild>,
+    ) {
+        // an outage, not a removal
+        if incomplete.unavailable {
+            return;
         }
-        while let Some(next) = add_user_roles_jobs.next().await {
-            if let Err(err) = next {
-                tracing::error!("update_database apply_role error: {err}");
-            }
+
+        tracing::info!("left guild_id={}", incomplete.id);
+
+        if let Err(err) = self
+            .db
+            .set_guild_left(incomplete.id, Some(expiry::now()))
+            .await
+        {
+            tracing::error!("failed to mark a left guild: {err}");
         }
+    }
 
-        Ok(())
+    async fn guild_role_create(&self, ctx: Context, new: Role) {
+        let lock = self.role_lock(new.guild_id);
+        let _guard = lock.lock().await;
+
+        if role_deleted(&ctx, &new) {
+            return;
+        }
+        self.sync_role(&new).await;
     }
 
-    pub async fn set_main_channel(&self, guild_id: GuildId, channel_id: ChannelId) -> Result<()> {
-        let rows = sqlx::query(
-            "
-UPDATE guilds
-SET main_channel_id = $2
-WHERE guild_id = $1
-            ",
-        )
-        .bind(guild_id.get() as i64)
-        .bind(channel_id.get() as i64)
-        .execute(&self.db)
-        .await?;
+    async fn guild_role_update(
+        &self,
+        ctx: Context,
+        _old_data_if_available: Option<Role>,
+        new: Role,
+    ) {
+        let lock = self.role_lock(new.guild_id);
+        let _guard = lock.lock().await;
 
-        tracing::debug!("set_main_channel rows affected: {}", rows.rows_affected());
-        Ok(())
+        if role_deleted(&ctx, &new) {
+            return;
+        }
+        self.sync_role(&new).await;
     }
 
-    pub async fn get_main_channel(&self, guild_id: GuildId) -> Result<Option<ChannelId>> {
-        let channel_id: Option<(Option<i64>,)> = sqlx::query_as(
-            "
-SELECT main_channel_id
-FROM guilds
-WHERE guild_id = $1
-            ",
-        )
-        .bind(guild_id.get() as i64)
-        .fetch_optional(&self.db)
-        .await?;
+    async fn guild_role_delete(
+        &self,
+        _ctx: Context,
+        guild_id: GuildId,
+        removed_role_id: RoleId,
+        _removed_role_data_if_available: Option<Role>,
+    ) {
+        let lock = self.role_lock(guild_id);
+        let _guard = lock.lock().await;
 
-        if let Some((Some(id),)) = channel_id {
-            Ok(Some(ChannelId::new(id as u64)))
-        } else {
-            Ok(None)
+        match self.db.forget_role(guild_id, removed_role_id).await {
+            Ok(true) => tracing::info!("role {removed_role_id} was deleted"),
+            Ok(false) => {}
+            Err(err) => tracing::error!("failed to forget a role: {err}"),
         }
     }
 
-    // pub async fn orphaned_roles(&self, ctx: Context, guild_id: GuildId) {
-    // }
-}
+    async fn guild_member_update(
+        &self,
+        ctx: Context,
+        _old_if_available: Option<Member>,
+        _new: Option<Member>,
+        event: GuildMemberUpdateEvent,
+    ) {
+        let (guild_id, user_id) = (event.guild_id, event.user.id);
+
+        // roles with permissions are not managed, so they never need writing
+        let managed: Vec<RoleId> = match ctx.cache.guild(guild_id) {
+            Some(guild) => event
+                .roles
+                .iter()
+                .filter(|role_id| {
+                    guild
+                        .roles
+                        .get(role_id)
+                        .is_some_and(|role| role.permissions == Permissions::empty())
+                })
+                .copied()
+                .collect(),
+            // the store skips roles it doesn't know
+            None => event.roles.clone(),
+        };
 
-#[async_trait]
-impl EventHandler for Handler {
-    async fn guild_member_addition(&self, ctx: Context, new_member: Member) {
-        let roles = sqlx::query_as(
-            "
-                SELECT role_id
-                FROM user_roles
-                WHERE user_id = $1
-            ",
-        )
-        .bind(new_member.user.id.get() as i64)
-        .fetch_all(&self.db)
-        .await;
-
-        let roles: Vec<(i64,)> = match roles {
-            Ok(roles) => roles,
+        // diff against the store instead of the cached member,
+        // the cache might not have it and the store is what has to change
+        let stored = match self.db.member_roles(guild_id, user_id).await {
+            Ok(stored) => stored,
             Err(err) => {
-                tracing::error!("failed to get user roles: {err}");
+                tracing::error!("failed to get member roles: {err}");
                 return;
             }
         };
 
-        for (role_id,) in roles {
-            _ = ctx
-                .http
-                .add_member_role(
-                    new_member.guild_id,
-                    new_member.user.id,
-                    RoleId::new(role_id as u64),
-                    Some("prevented rejoin role removal"),
-                )
-                .await;
+        let added: Vec<RoleId> = managed
+            .iter()
+            .filter(|role_id| !stored.contains(role_id))
+            .copied()
+            .collect();
+        let removed: Vec<RoleId> = stored
+            .iter()
+            .filter(|role_id| !event.roles.contains(role_id))
+            .copied()
+            .collect();
+
+        if !added.is_empty()
+            && let Err(err) = self.db.add_member_roles(guild_id, user_id, &added).await
+        {
+            tracing::error!("failed to add member roles: {err}");
+        }
+        if !removed.is_empty()
+            && let Err(err) = self
+                .db
+                .remove_member_roles(guild_id, user_id, &removed)
+                .await
+        {
+            tracing::error!("failed to remove member roles: {err}");
+        }
+    }
+
+    async fn guild_member_removal(
+        &self,
+        _ctx: Context,
+        guild_id: GuildId,
+        user: User,
+        _member_data_if_available: Option<Member>,
+    ) {
+        // only owners need to be tracked
+        match self.db.list_count(guild_id, user.id).await {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(err) => {
+                tracing::error!("failed to list owned roles: {err}");
+                return;
+            }
+        }
+
+        if let Err(err) = self
+            .db
+            .record_departure(guild_id, user.id, expiry::now())
+            .await
+        {
+            tracing::error!("failed to record a departure: {err}");
         }
     }
 
     async fn message(&self, ctx: Context, new_message: Message) {
+        self.reward_message(&new_message).await;
+
+        let Ok(config) = self
+            .guild_config(new_message.guild_id)
+            .await
+            .inspect_err(|err| tracing::error!("failed to get guild settings: {err}"))
+        else {
+            return;
+        };
+        if !config.u_echo {
+            return;
+        }
+
         let mut last_u = self.last_u.lock().await;
         if new_message.content.as_str() != "u" || new_message.author.bot {
             *last_u = None;
@@ -541,8 +576,19 @@ impl EventHandler for Handler {
         ctx: Context,
         old_if_available: Option<Message>,
         new: Option<Message>,
-        _event: MessageUpdateEvent,
+        event: MessageUpdateEvent,
     ) {
+        let Ok(config) = self
+            .guild_config(event.guild_id)
+            .await
+            .inspect_err(|err| tracing::error!("failed to get guild settings: {err}"))
+        else {
+            return;
+        };
+        if !config.anti_censor {
+            return;
+        }
+
         let Some(old_if_available) = old_if_available else {
             return;
         };
@@ -555,13 +601,30 @@ impl EventHandler for Handler {
             }
         }
 
-        if let Err(err) = old_if_available
-            .reply(
-                &ctx.http,
-                format!("anti-censoring: {}", old_if_available.content.as_str()),
-            )
-            .await
-        {
+        let channel_id = self
+            .announcement_channel(event.guild_id, old_if_available.channel_id)
+            .await;
+
+        let result = if channel_id == old_if_available.channel_id {
+            old_if_available
+                .reply(
+                    &ctx.http,
+                    format!("anti-censoring: {}", old_if_available.content.as_str()),
+                )
+                .await
+        } else {
+            channel_id
+                .send_message(
+                    &ctx.http,
+                    CreateMessage::new().content(format!(
+                        "anti-censoring {}: {}",
+                        old_if_available.link(),
+                        old_if_available.content.as_str()
+                    )),
+                )
+                .await
+        };
+        if let Err(err) = result {
             tracing::error!("failed to reply: {err}");
         }
     }
@@ -571,8 +634,19 @@ impl EventHandler for Handler {
         ctx: Context,
         channel_id: ChannelId,
         deleted_message_id: MessageId,
-        _guild_id: Option<GuildId>,
+        guild_id: Option<GuildId>,
     ) {
+        let Ok(config) = self
+            .guild_config(guild_id)
+            .await
+            .inspect_err(|err| tracing::error!("failed to get guild settings: {err}"))
+        else {
+            return;
+        };
+        if !config.delete_logging {
+            return;
+        }
+
         fn get_msg(
             ctx: &Context,
             channel_id: ChannelId,
@@ -588,11 +662,18 @@ impl EventHandler for Handler {
             return;
         };
 
-        if let Err(err) = channel_id
+        let target = self.announcement_channel(guild_id, channel_id).await;
+        let location = if target == channel_id {
+            String::new()
+        } else {
+            format!(" in <#{channel_id}>")
+        };
+
+        if let Err(err) = target
             .send_message(
                 &ctx.http,
                 CreateMessage::new().content(format!(
-                    "<@{}> tried to delete this message: {}",
+                    "<@{}> tried to delete this message{location}: {}",
                     old_if_available.author.id, old_if_available.content,
                 )),
             )
@@ -603,8 +684,13 @@ impl EventHandler for Handler {
     }
 
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        let Interaction::Command(command) = interaction else {
-            return;
+        let command = match interaction {
+            Interaction::Command(command) => command,
+            Interaction::Component(component) => {
+                self.component_interaction(&ctx, &component).await;
+                return;
+            }
+            _ => return,
         };
 
         let Some(guild_id) = command.guild_id else {
@@ -622,11 +708,22 @@ impl EventHandler for Handler {
             "take_ownership" => take_ownership::run(self, &ctx, &command, guild_id).await,
             "create" => create::run(self, &ctx, &command, guild_id).await,
             "delete" => delete::run(self, &ctx, &command, guild_id).await,
+            "abandon" => abandon::run(self, &ctx, &command, guild_id).await,
+            "extend" => extend::run(self, &ctx, &command, guild_id).await,
+            "give" => give::run(self, &ctx, &command, guild_id).await,
             "list" => list::run(self, &ctx, &command, guild_id).await,
             "orphaned" => orphaned::run(self, &ctx, &command, guild_id).await,
+            "sticky" => sticky::run(self, &ctx, &command, guild_id).await,
             "add" => add::run(self, &ctx, &command, guild_id).await,
             "query" => query::run(self, &ctx, &command, guild_id).await,
             "remove" => remove::run(self, &ctx, &command, guild_id).await,
+            "settings" => settings::run(self, &ctx, &command, guild_id).await,
+            "main_channel" => main_channel::run(self, &ctx, &command, guild_id).await,
+            "sync_status" => sync_status::run(self, &ctx, &command, guild_id).await,
+            "balance" => balance::run(self, &ctx, &command, guild_id).await,
+            "transfer" => transfer::run(self, &ctx, &command, guild_id).await,
+            "grant" => grant::run(self, &ctx, &command, guild_id).await,
+            "ledger" => ledger::run(self, &ctx, &command, guild_id).await,
 
             _ => Err("???".to_string()),
         };
@@ -646,7 +743,7 @@ impl EventHandler for Handler {
             return;
         }
 
-        time::sleep(Duration::from_secs(120)).await;
+        time::sleep(self.config.error_reply_delete_delay()).await;
 
         if let Err(err) = command.delete_response(&ctx.http).await {
             tracing::error!("failed to delete the response: {err}");
@@ -657,29 +754,11 @@ impl EventHandler for Handler {
         let handler = self.me.upgrade().unwrap();
         tracing::info!("{} is connected", ready.user.name);
 
-        let mut bulk_update_database = FuturesUnordered::new();
-        for guild_id in ctx.cache.guilds() {
-            tracing::info!("guild_id={guild_id}");
-
-            let ctx = ctx.clone();
-            let handler = handler.clone();
-
-            bulk_update_database.push(tokio::spawn(async move {
-                let result = handler.update_database(&ctx, guild_id).await;
-                (result, guild_id)
-            }));
-        }
-        while let Some(next) = bulk_update_database.next().await {
-            match next {
-                Ok((Ok(_), _)) => {}
-                Ok((Err(err), guild_id)) => {
-                    tracing::error!("failed to update guild_id={guild_id}: {err}");
-                }
-                Err(err) => {
-                    tracing::error!("failed to update guild: {err}");
-                }
-            }
-        }
+        tokio::spawn(sync::startup(
+            handler.clone(),
+            ctx.http.clone(),
+            ctx.cache.guilds(),
+        ));
 
         if let Ok(commands) = ctx
             .http
@@ -692,11 +771,22 @@ impl EventHandler for Handler {
                     "take_ownership",
                     "create",
                     "delete",
+                    "abandon",
+                    "extend",
+                    "give",
                     "list",
                     "orphaned",
+                    "sticky",
                     "add",
                     "query",
                     "remove",
+                    "settings",
+                    "main_channel",
+                    "sync_status",
+                    "balance",
+                    "transfer",
+                    "grant",
+                    "ledger",
                 ]
                 .contains(&command.name.as_str())
                 {
@@ -717,18 +807,25 @@ impl EventHandler for Handler {
             ("take_ownership", take_ownership::register()),
             ("create", create::register()),
             ("delete", delete::register()),
+            ("abandon", abandon::register()),
+            ("extend", extend::register()),
+            ("give", give::register()),
             ("list", list::register()),
             ("orphaned", orphaned::register()),
+            ("sticky", sticky::register()),
             ("add", add::register()),
             ("query", query::register()),
             ("remove", remove::register()),
+            ("settings", settings::register()),
+            ("main_channel", main_channel::register()),
+            ("sync_status", sync_status::register()),
+            ("balance", balance::register()),
+            ("transfer", transfer::register()),
+            ("grant", grant::register()),
+            ("ledger", ledger::register()),
             // ("add", add::register()),
             // ("new_role", new_role::register()),
             // ("remove", remove::register()),
-            // ("balance", balance::register()),
-            // ("transfer", transfer::register()),
-            // ("main_channel", main_channel::register()),
-            // ("extend", extend::register()),
         ] {
             tracing::debug!("registering command {name}");
             if let Err(err) = Command::create_global_command(&ctx.http, cmd).await {
@@ -742,6 +839,33 @@ impl EventHandler for Handler {
 
 //
 
+/// periodically forget message reward times that no longer hold back a reward
+async fn prune_rewards(handler: Arc<Handler>) {
+    let cooldown = handler.config.message_reward_cooldown();
+    let mut interval = time::interval(cooldown.max(Duration::from_secs(60)));
+    loop {
+        interval.tick().await;
+        handler
+            .last_reward
+            .retain(|_, last| last.elapsed() < cooldown);
+    }
+}
+
+/// the cache is updated before the events are dispatched, so a role that is
+/// missing from it was deleted while its event waited for the role lock,
+/// like the role of a failed `/create`
+fn role_deleted(ctx: &Context, role: &Role) -> bool {
+    let Some(guild) = ctx.cache.guild(role.guild_id) else {
+        return false;
+    };
+
+    let deleted = !guild.roles.contains_key(&role.id);
+    if deleted {
+        tracing::debug!("role {} was deleted before it could be synced", role.id);
+    }
+    deleted
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
@@ -750,33 +874,45 @@ async fn main() -> Result<()> {
     tracing::debug!("init");
 
     let token = env::var("TOKEN")?;
-    let pg_addr = env::var("PG_ADDR")?;
+    let db_url = env::var("DATABASE_URL").or_else(|_| env::var("PG_ADDR"))?;
 
-    let intents = GatewayIntents::GUILDS
-        | GatewayIntents::GUILD_MEMBERS
-        | GatewayIntents::MESSAGE_CONTENT
-        | GatewayIntents::GUILD_MESSAGES
-        | GatewayIntents::DIRECT_MESSAGES;
+    let config_path = env::var("CONFIG").unwrap_or_else(|_| "config.ron".to_string());
+    let config = Config::load(config_path)?;
+    let intents = config.intents()?;
 
-    let db = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&pg_addr)
-        .await?;
+    let db = store::connect(&db_url, config.max_connections).await?;
+    db.migrate().await?;
+    store::verify_schema(&*db).await?;
+
+    let mut settings = Settings::default();
+    settings.max_messages = config.max_messages;
 
     let handler = Arc::new_cyclic(|me| Handler {
         me: me.clone(),
         db,
+        config,
+        guild_settings: DashMap::new(),
+        main_channels: DashMap::new(),
+        sync_status: DashMap::new(),
+        role_locks: DashMap::new(),
+        last_reward: DashMap::new(),
         last_u: Mutex::new(None),
     });
 
-    let mut settings = Settings::default();
-    settings.max_messages = 256;
-
     let mut client = Client::builder(&token, intents)
-        .event_handler_arc(handler)
+        .event_handler_arc(handler.clone())
         .cache_settings(settings)
         .await?;
 
+    tokio::spawn(expiry::run(handler.clone(), client.http.clone()));
+    tokio::spawn(departure::run(handler.clone(), client.http.clone()));
+    tokio::spawn(prune_rewards(handler.clone()));
+    tokio::spawn(sync::run(
+        handler,
+        client.cache.clone(),
+        client.http.clone(),
+    ));
+
     tokio::select! {
         r = signal::ctrl_c() => r?,
         r = client.start() => r?,
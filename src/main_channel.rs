@@ -1,26 +1,25 @@
-use serenity::all::{CommandInteraction, Context, CreateCommand};
+use serenity::all::{CommandInteraction, Context, CreateCommand, GuildId, Permissions};
 
 use crate::Handler;
 
 //
 
 pub fn register() -> CreateCommand {
-    CreateCommand::new("main_channel").description("Set this channel as the guild main channel")
+    CreateCommand::new("main_channel")
+        .description("Set this channel as the guild main channel")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
 }
 
 pub async fn run(
     handler: &Handler,
     _: &Context,
     interaction: &CommandInteraction,
+    guild_id: GuildId,
 ) -> Result<String, String> {
     let Some(member) = interaction.member.as_deref() else {
         return Err("not in a guild".to_string());
     };
 
-    let Some(guild_id) = interaction.guild_id else {
-        return Err("not in a guild".to_string());
-    };
-
     let Some(permissions) = member.permissions else {
         tracing::error!("member.permissions should always be Some in commands");
         return Err("internal error".to_string());
@@ -33,6 +33,7 @@ pub async fn run(
 
     if just_count {
         let Ok(list) = handler
+            .db
             .orphaned_count(guild_id)
             .await
             .inspect_err(|err| tracing::error!("failed to get a count of orphaned roles: {err}"))
@@ -42,10 +43,10 @@ pub async fn run(
 
         Ok(format!("there are {list} orphaned roles"))
     } else {
-        let Ok(list) = handler
-            .orphaned(guild_id)
-            .await
-            .inspect_err(|err| tracing::error!("failed to get a list of orphaned roles: {err}"))
+        let Ok(list) =
+            handler.db.orphaned(guild_id).await.inspect_err(|err| {
+                tracing::error!("failed to get a list of orphaned roles: {err}")
+            })
         else {
             return Err("internal error".to_string());
         };
@@ -56,7 +57,7 @@ pub async fn run(
 
         let mut buf = String::new();
 
-        for (role,) in list {
+        for role in list {
             use std::fmt::Write;
             let len = buf.len();
             _ = writeln!(&mut buf, " - {role}");
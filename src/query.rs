@@ -3,7 +3,7 @@ use serenity::all::{
     ResolvedOption, ResolvedValue,
 };
 
-use crate::{Handler, QueryRoleResult};
+use crate::{Handler, store::QueryRoleResult};
 
 //
 
@@ -31,7 +31,7 @@ pub async fn run(
         return Err("missing role".to_string());
     };
 
-    match handler.query_role(guild_id, role.id).await {
+    match handler.db.query_role(guild_id, role.id).await {
         Err(err) => {
             tracing::error!("failed to query role: {err}");
             Err("internal error".to_string())
@@ -0,0 +1,350 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, GuildId,
+    Permissions, ResolvedOption, ResolvedValue, RoleId,
+};
+
+use crate::{Handler, config::RestoreRoles, store::GuildSettings};
+
+//
+
+/// numbers are stored as `int` columns
+const MAX_VALUE: i32 = i32::MAX;
+
+const U32_SETTINGS: &[(&str, &str)] = &[
+    (
+        "max_owned_roles",
+        "How many roles a single user can own, 0 is unlimited",
+    ),
+    ("role_cost", "Price of /create, 0 is free"),
+    ("extend_cost", "Price of /extend, 0 is free"),
+    (
+        "message_reward",
+        "Currency earned by sending a message, 0 turns rewards off",
+    ),
+];
+
+const BOOL_SETTINGS: &[(&str, &str)] = &[
+    (
+        "anti_censor",
+        "Reply with the original content of edited messages",
+    ),
+    ("delete_logging", "Repost deleted messages"),
+    ("u_echo", "Reply \"u\" when two different users say \"u\""),
+    ("hoist", "Display new roles separately"),
+    ("mentionable", "Allow anyone to mention new roles"),
+    (
+        "delete_departed_roles",
+        "Delete the roles of owners who left instead of orphaning them",
+    ),
+];
+
+pub fn register() -> CreateCommand {
+    let mut command = CreateCommand::new("settings")
+        .description("View or change the guild settings")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::SubCommand,
+            "view",
+            "Show the current settings",
+        ))
+        .add_option(restore_roles())
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "role_limit",
+                "Let members with a role own more roles, 0 is unlimited",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(CommandOptionType::Role, "role", "target role")
+                    .required(true),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "value",
+                    "new value, leave empty to remove",
+                )
+                .min_int_value(0)
+                .max_int_value(MAX_VALUE as u64),
+            ),
+        );
+
+    for (name, description) in U32_SETTINGS {
+        command = command.add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, *name, *description)
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "value",
+                        "new value, leave empty to reset",
+                    )
+                    .min_int_value(0)
+                    .max_int_value(MAX_VALUE as u64),
+                ),
+        );
+    }
+
+    for (name, description) in BOOL_SETTINGS {
+        command = command.add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, *name, *description)
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "value",
+                    "new value, leave empty to reset",
+                )),
+        );
+    }
+
+    command
+}
+
+fn restore_roles() -> CreateCommandOption {
+    let mut value = CreateCommandOption::new(
+        CommandOptionType::String,
+        "value",
+        "new value, leave empty to reset",
+    );
+    for mode in RestoreRoles::ALL {
+        value = value.add_string_choice(mode.name(), mode.name());
+    }
+
+    CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "restore_roles",
+        "Which roles members get back when they rejoin",
+    )
+    .add_sub_option(value)
+}
+
+pub async fn run(
+    handler: &Handler,
+    _: &Context,
+    interaction: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let Some(member) = interaction.member.as_deref() else {
+        return Err("not in a guild".to_string());
+    };
+
+    let Some(permissions) = member.permissions else {
+        tracing::error!("member.permissions should always be Some in commands");
+        return Err("internal error".to_string());
+    };
+
+    if !permissions.administrator() {
+        return Err("permission denied".to_string());
+    }
+
+    let options = interaction.data.options();
+
+    let Some(ResolvedOption {
+        name,
+        value: ResolvedValue::SubCommand(sub_options),
+        ..
+    }) = options.first()
+    else {
+        return Err("missing setting".to_string());
+    };
+
+    if *name == "role_limit" {
+        set_role_limit(handler, guild_id, sub_options).await?;
+    }
+
+    let Ok(mut settings) = handler
+        .db
+        .guild_settings(guild_id)
+        .await
+        .inspect_err(|err| tracing::error!("failed to get guild settings: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    let Ok(role_limits) = handler
+        .db
+        .role_limits(guild_id)
+        .await
+        .inspect_err(|err| tracing::error!("failed to get role limits: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    match *name {
+        "view" => return Ok(describe(handler, &settings, &role_limits)),
+        "role_limit" => {
+            return Ok(format!(
+                "{name} updated\n{}",
+                describe(handler, &settings, &role_limits)
+            ));
+        }
+        _ => {}
+    }
+
+    let value = sub_options.first().map(|o| &o.value);
+    match (*name, value) {
+        ("restore_roles", Some(ResolvedValue::String(mode))) => {
+            let Ok(mode) = RestoreRoles::from_name(mode) else {
+                return Err("invalid restore_roles".to_string());
+            };
+            settings.restore_roles = Some(mode);
+        }
+        ("restore_roles", None) => {
+            settings.restore_roles = None;
+        }
+        (name, value) => {
+            if let Some(setting) = u32_setting(&mut settings, name) {
+                *setting = match value {
+                    Some(ResolvedValue::Integer(value)) => match u32_value(*value) {
+                        Some(value) => Some(value),
+                        None => return Err(format!("invalid {name}")),
+                    },
+                    None => None,
+                    _ => return Err(format!("invalid {name}")),
+                };
+            } else if let Some(setting) = bool_setting(&mut settings, name) {
+                *setting = match value {
+                    Some(ResolvedValue::Boolean(value)) => Some(*value),
+                    None => None,
+                    _ => return Err(format!("invalid {name}")),
+                };
+            } else {
+                return Err("unknown setting".to_string());
+            }
+        }
+    }
+
+    if let Err(err) = handler.set_guild_settings(guild_id, settings.clone()).await {
+        tracing::error!("failed to set guild settings: {err}");
+        return Err("internal error".to_string());
+    }
+
+    Ok(format!(
+        "{name} updated\n{}",
+        describe(handler, &settings, &role_limits)
+    ))
+}
+
+async fn set_role_limit(
+    handler: &Handler,
+    guild_id: GuildId,
+    options: &[ResolvedOption<'_>],
+) -> Result<(), String> {
+    let mut role = None;
+    let mut value = None;
+    for option in options {
+        match (option.name, &option.value) {
+            ("role", ResolvedValue::Role(r)) => role = Some(r),
+            ("value", ResolvedValue::Integer(v)) => value = Some(*v),
+            _ => {}
+        }
+    }
+
+    let Some(role) = role else {
+        return Err("missing role".to_string());
+    };
+
+    let value = match value.map(u32_value) {
+        Some(Some(value)) => Some(value),
+        Some(None) => return Err("invalid role_limit".to_string()),
+        None => None,
+    };
+
+    if let Err(err) = handler.db.set_role_limit(guild_id, role.id, value).await {
+        tracing::error!("failed to set role limit: {err}");
+        return Err("internal error".to_string());
+    }
+
+    Ok(())
+}
+
+/// `value` if it is in `0..=MAX_VALUE`
+fn u32_value(value: i64) -> Option<u32> {
+    u32::try_from(value)
+        .ok()
+        .filter(|value| *value <= MAX_VALUE as u32)
+}
+
+fn u32_setting<'a>(settings: &'a mut GuildSettings, name: &str) -> Option<&'a mut Option<u32>> {
+    match name {
+        "max_owned_roles" => Some(&mut settings.max_owned_roles),
+        "role_cost" => Some(&mut settings.role_cost),
+        "extend_cost" => Some(&mut settings.extend_cost),
+        "message_reward" => Some(&mut settings.message_reward),
+        _ => None,
+    }
+}
+
+fn bool_setting<'a>(settings: &'a mut GuildSettings, name: &str) -> Option<&'a mut Option<bool>> {
+    match name {
+        "anti_censor" => Some(&mut settings.anti_censor),
+        "delete_logging" => Some(&mut settings.delete_logging),
+        "u_echo" => Some(&mut settings.u_echo),
+        "hoist" => Some(&mut settings.hoist),
+        "mentionable" => Some(&mut settings.mentionable),
+        "delete_departed_roles" => Some(&mut settings.delete_departed_roles),
+        _ => None,
+    }
+}
+
+fn describe(handler: &Handler, settings: &GuildSettings, role_limits: &[(RoleId, u32)]) -> String {
+    use std::fmt::Write;
+
+    fn source<T>(setting: Option<T>) -> &'static str {
+        if setting.is_some() { "" } else { " (default)" }
+    }
+
+    fn limit(max: u32) -> String {
+        if max == 0 {
+            "unlimited".to_string()
+        } else {
+            max.to_string()
+        }
+    }
+
+    let config = handler.config.resolve(settings);
+    let mut buf = String::new();
+    _ = writeln!(
+        &mut buf,
+        " - max_owned_roles: {}{}",
+        limit(config.max_owned_roles),
+        source(settings.max_owned_roles)
+    );
+    for (role_id, max) in role_limits {
+        _ = writeln!(&mut buf, " - role_limit <@&{role_id}>: {}", limit(*max));
+    }
+    for (name, value, setting) in [
+        ("role_cost", config.role_cost, settings.role_cost),
+        ("extend_cost", config.extend_cost, settings.extend_cost),
+        (
+            "message_reward",
+            config.message_reward,
+            settings.message_reward,
+        ),
+    ] {
+        _ = writeln!(&mut buf, " - {name}: {value}{}", source(setting));
+    }
+    _ = writeln!(
+        &mut buf,
+        " - restore_roles: {}{}",
+        config.restore_roles.name(),
+        source(settings.restore_roles)
+    );
+    for (name, value, setting) in [
+        ("anti_censor", config.anti_censor, settings.anti_censor),
+        (
+            "delete_logging",
+            config.delete_logging,
+            settings.delete_logging,
+        ),
+        ("u_echo", config.u_echo, settings.u_echo),
+        ("hoist", config.hoist, settings.hoist),
+        ("mentionable", config.mentionable, settings.mentionable),
+        (
+            "delete_departed_roles",
+            config.delete_departed_roles,
+            settings.delete_departed_roles,
+        ),
+    ] {
+        _ = writeln!(&mut buf, " - {name}: {value}{}", source(setting));
+    }
+    buf
+}
@@ -0,0 +1,98 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, GuildId,
+    ResolvedValue,
+};
+
+use crate::{Handler, config::RestoreRoles};
+
+//
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sticky")
+        .description("Choose if members get a role back when they rejoin")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Role, "role", "target role").required(true),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "value",
+                "give the role back on rejoin, leave out to follow restore_roles",
+            )
+            .required(false),
+        )
+}
+
+pub async fn run(
+    handler: &Handler,
+    _: &Context,
+    interaction: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let Some(member) = interaction.member.as_deref() else {
+        return Err("not in a guild".to_string());
+    };
+
+    let Some(permissions) = member.permissions else {
+        tracing::error!("member.permissions should always be Some in commands");
+        return Err("internal error".to_string());
+    };
+
+    let mut role = None;
+    let mut sticky = None;
+    for option in interaction.data.options() {
+        match (option.name, option.value) {
+            ("role", ResolvedValue::Role(r)) => role = Some(r),
+            ("value", ResolvedValue::Boolean(v)) => sticky = Some(v),
+            _ => {}
+        }
+    }
+
+    let Some(role) = role else {
+        return Err("missing role".to_string());
+    };
+    // admins can change any role, everyone else only their own
+    let owner_user_id = (!permissions.administrator()).then_some(interaction.user.id);
+
+    let Ok(success) = handler
+        .db
+        .set_sticky(guild_id, role.id, owner_user_id, sticky)
+        .await
+        .inspect_err(|err| tracing::error!("failed to set sticky: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    if !success {
+        return Err(if owner_user_id.is_some() {
+            "role not owned".to_string()
+        } else {
+            "role is not controlled by me".to_string()
+        });
+    }
+
+    let Ok(config) = handler
+        .guild_config(Some(guild_id))
+        .await
+        .inspect_err(|err| tracing::error!("failed to get guild config: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    let state = match sticky {
+        Some(true) => "sticky",
+        Some(false) => "not sticky",
+        None => "unmarked",
+    };
+    let restored = match config.restore_roles {
+        RestoreRoles::On => sticky != Some(false),
+        RestoreRoles::Sticky => sticky == Some(true),
+        RestoreRoles::Off => false,
+    };
+    let restored = if restored { "is" } else { "is not" };
+    Ok(format!(
+        "role {} is now {state}, it {restored} restored on rejoin while restore_roles is `{}`",
+        role.name,
+        config.restore_roles.name()
+    ))
+}
@@ -0,0 +1,585 @@
+use std::{collections::HashSet, fmt::Write};
+
+use color_eyre::eyre::{Result, eyre};
+use serenity::{
+    all::{ChannelId, GuildId, RoleId, UserId},
+    async_trait,
+};
+use sqlx::postgres::PgPoolOptions;
+
+use crate::config::RestoreRoles;
+
+//
+
+mod memory;
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use memory::MemoryStore;
+pub use postgres::PgStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+//
+
+/// per guild overrides of the `config.ron` values,
+/// `None` uses the config value
+#[derive(Debug, Clone, Default)]
+pub struct GuildSettings {
+    pub max_owned_roles: Option<u32>,
+    pub anti_censor: Option<bool>,
+    pub delete_logging: Option<bool>,
+    pub u_echo: Option<bool>,
+    pub hoist: Option<bool>,
+    pub mentionable: Option<bool>,
+    pub delete_departed_roles: Option<bool>,
+    pub restore_roles: Option<RestoreRoles>,
+    pub role_cost: Option<u32>,
+    pub extend_cost: Option<u32>,
+    pub message_reward: Option<u32>,
+}
+
+/// an owned role with an expiry deadline
+#[derive(Debug, Clone, Copy)]
+pub struct ExpiringRole {
+    pub guild_id: GuildId,
+    pub role_id: RoleId,
+    pub owner_user_id: UserId,
+    /// unix timestamp
+    pub expires_at: i64,
+}
+
+/// why a balance changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerReason {
+    /// earned by sending a message
+    MessageReward,
+    /// granted or taken away by an admin
+    Grant { admin_user_id: UserId },
+    /// sent to or received from another user
+    Transfer { other_user_id: UserId },
+    /// paid for `/create`
+    CreateRole { role_id: RoleId },
+    /// paid for `/extend`
+    ExtendRole { role_id: RoleId },
+}
+
+impl LedgerReason {
+    /// the `reason` column
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::MessageReward => "message_reward",
+            Self::Grant { .. } => "grant",
+            Self::Transfer { .. } => "transfer",
+            Self::CreateRole { .. } => "create_role",
+            Self::ExtendRole { .. } => "extend_role",
+        }
+    }
+
+    /// the `role_id` column
+    pub fn role_id(&self) -> Option<RoleId> {
+        match self {
+            Self::CreateRole { role_id } | Self::ExtendRole { role_id } => Some(*role_id),
+            _ => None,
+        }
+    }
+
+    /// the `other_user_id` column
+    pub fn other_user_id(&self) -> Option<UserId> {
+        match self {
+            Self::Grant { admin_user_id } => Some(*admin_user_id),
+            Self::Transfer { other_user_id } => Some(*other_user_id),
+            _ => None,
+        }
+    }
+
+    /// inverse of [`Self::kind`], [`Self::role_id`] and [`Self::other_user_id`]
+    pub fn from_columns(
+        kind: &str,
+        role_id: Option<i64>,
+        other_user_id: Option<i64>,
+    ) -> Result<Self> {
+        let role_id = || {
+            role_id
+                .map(|id| RoleId::new(id as u64))
+                .ok_or_else(|| eyre!("ledger entry `{kind}` is missing role_id"))
+        };
+        let other_user_id = || {
+            other_user_id
+                .map(|id| UserId::new(id as u64))
+                .ok_or_else(|| eyre!("ledger entry `{kind}` is missing other_user_id"))
+        };
+
+        Ok(match kind {
+            "message_reward" => Self::MessageReward,
+            "grant" => Self::Grant {
+                admin_user_id: other_user_id()?,
+            },
+            "transfer" => Self::Transfer {
+                other_user_id: other_user_id()?,
+            },
+            "create_role" => Self::CreateRole {
+                role_id: role_id()?,
+            },
+            "extend_role" => Self::ExtendRole {
+                role_id: role_id()?,
+            },
+            _ => return Err(eyre!("unknown ledger entry `{kind}`")),
+        })
+    }
+}
+
+/// one balance change of a user
+#[derive(Debug, Clone)]
+pub struct LedgerEntry {
+    pub user_id: UserId,
+    /// negative if the balance went down
+    pub amount: i64,
+    pub reason: LedgerReason,
+    /// unix timestamp
+    pub created_at: i64,
+}
+
+pub enum QueryRoleResult {
+    Owned(UserId),
+    Orphan,
+    NotFound,
+}
+
+/// every table and column the queries rely on
+pub const SCHEMA: &[(&str, &[&str])] = &[
+    (
+        "guilds",
+        &["guild_id", "main_channel_id", "synced_at", "left_at"],
+    ),
+    (
+        "roles",
+        &[
+            "role_id",
+            "guild_id",
+            "name",
+            "owner_user_id",
+            "expires_at",
+            "expiry_warned",
+            "sticky",
+        ],
+    ),
+    ("users", &["user_id", "guild_id", "balance"]),
+    ("user_roles", &["user_id", "guild_id", "role_id"]),
+    (
+        "ledger",
+        &[
+            "entry_id",
+            "guild_id",
+            "user_id",
+            "amount",
+            "reason",
+            "role_id",
+            "other_user_id",
+            "created_at",
+        ],
+    ),
+    (
+        "guild_settings",
+        &[
+            "guild_id",
+            "max_owned_roles",
+            "anti_censor",
+            "delete_logging",
+            "u_echo",
+            "hoist",
+            "mentionable",
+            "delete_departed_roles",
+            "restore_roles",
+            "role_cost",
+            "extend_cost",
+            "message_reward",
+        ],
+    ),
+    ("departures", &["guild_id", "user_id", "left_at"]),
+    ("role_limits", &["guild_id", "role_id", "max_owned_roles"]),
+];
+
+/// connect to the store selected by the url scheme,
+/// `memory:` selects the in-memory store, `sqlite:` selects sqlite
+/// and everything else goes to postgres
+pub async fn connect(url: &str, max_connections: u32) -> Result<Box<dyn RoleStore>> {
+    if url.starts_with("memory:") {
+        tracing::warn!("using the in-memory store, nothing will be persisted");
+        return Ok(Box::new(MemoryStore::new()));
+    }
+
+    if url.starts_with("sqlite:") {
+        #[cfg(feature = "sqlite")]
+        return Ok(Box::new(SqliteStore::connect(url, max_connections).await?));
+        #[cfg(not(feature = "sqlite"))]
+        return Err(eyre!("sqlite support requires the `sqlite` feature"));
+    }
+
+    let db = PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect(url)
+        .await?;
+    Ok(Box::new(PgStore::new(db)))
+}
+
+/// check that the store has every table and column from [`SCHEMA`]
+pub async fn verify_schema(db: &dyn RoleStore) -> Result<()> {
+    let columns: HashSet<(String, String)> = db.columns().await?.into_iter().collect();
+    let tables: HashSet<&str> = columns.iter().map(|(table, _)| table.as_str()).collect();
+
+    let mut missing = String::new();
+    for (table, table_columns) in SCHEMA {
+        if !tables.contains(table) {
+            _ = writeln!(&mut missing, " - table `{table}`");
+            continue;
+        }
+
+        for column in *table_columns {
+            if !columns.contains(&(table.to_string(), column.to_string())) {
+                _ = writeln!(&mut missing, " - column `{table}.{column}`");
+            }
+        }
+    }
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    Err(eyre!(
+        "the database schema is missing:\n{missing}\
+        the migrations did not create these, \
+        check that DATABASE_URL points to the bot database \
+        and that its `_sqlx_migrations` table matches the schema"
+    ))
+}
+
+/// persistent storage of guilds, roles, users, user roles and balances
+///
+/// every balance change is recorded in the ledger
+/// in the same transaction as the change itself
+#[async_trait]
+pub trait RoleStore: Send + Sync {
+    /// apply all pending schema migrations
+    async fn migrate(&self) -> Result<()>;
+
+    /// every (table, column) pair currently in the store
+    async fn columns(&self) -> Result<Vec<(String, String)>>;
+
+    /// returns true on success
+    async fn create_guild(&self, guild_id: GuildId) -> Result<bool>;
+
+    /// insert an unowned role or rename an existing one
+    async fn upsert_role(&self, guild_id: GuildId, role_id: RoleId, name: &str) -> Result<()>;
+
+    /// stop managing a role, regardless of its owner,
+    /// returns true if it was managed
+    async fn forget_role(&self, guild_id: GuildId, role_id: RoleId) -> Result<bool>;
+
+    /// fails if the owner already owns `max_owned` roles, `None` is unlimited,
+    /// or if the owner cannot pay `cost`,
+    /// `expires_at` is a unix timestamp, `None` never expires,
+    /// returns true on success
+    #[allow(clippy::too_many_arguments)]
+    async fn create_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        name: &str,
+        owner_user_id: UserId,
+        max_owned: Option<u32>,
+        expires_at: Option<i64>,
+        cost: i64,
+    ) -> Result<bool>;
+
+    /// returns true on success
+    async fn delete_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+    ) -> Result<bool>;
+
+    /// remove the owner of a role owned by `user_id` without deleting it,
+    /// returns true on success
+    async fn abandon_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+    ) -> Result<bool>;
+
+    /// mark a role to be restored or not when its members rejoin,
+    /// `None` leaves it to `restore_roles`,
+    /// `owner_user_id` limits it to a role owned by that user, `None` is for admins,
+    /// returns true on success
+    async fn set_sticky(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        owner_user_id: Option<UserId>,
+        sticky: Option<bool>,
+    ) -> Result<bool>;
+
+    async fn list_count(&self, guild_id: GuildId, user_id: UserId) -> Result<usize>;
+
+    async fn list(&self, guild_id: GuildId, user_id: UserId) -> Result<Vec<String>>;
+
+    async fn orphaned_count(&self, guild_id: GuildId) -> Result<usize>;
+
+    async fn orphaned(&self, guild_id: GuildId) -> Result<Vec<String>>;
+
+    /// fails if the user already owns `max_owned` roles, `None` is unlimited,
+    /// `expires_at` is a unix timestamp, `None` never expires,
+    /// returns true on success
+    async fn take_ownership(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+        max_owned: Option<u32>,
+        expires_at: Option<i64>,
+    ) -> Result<bool>;
+
+    /// move a role from its owner `from_user_id` to `to_user_id`,
+    /// fails if `to_user_id` already owns `max_owned` roles, `None` is unlimited,
+    /// returns true on success
+    async fn give_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        from_user_id: UserId,
+        to_user_id: UserId,
+        max_owned: Option<u32>,
+    ) -> Result<bool>;
+
+    /// push the expiry of a role owned by `user_id` to `lifetime` seconds
+    /// after its current expiry, or after `now` if it already passed,
+    /// only roles expiring at or before `due_before` can be extended,
+    /// so the expiry can't be pushed out more than once,
+    /// roles that never expire are left alone,
+    /// fails if the owner cannot pay `cost`,
+    /// returns the new expiry on success
+    #[allow(clippy::too_many_arguments)]
+    async fn extend_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+        now: i64,
+        due_before: i64,
+        lifetime: i64,
+        cost: i64,
+    ) -> Result<Option<i64>>;
+
+    /// owned roles in every guild that expire at or before `before`
+    /// and whose owner has not been warned yet
+    async fn expiring_roles(&self, before: i64) -> Result<Vec<ExpiringRole>>;
+
+    async fn set_expiry_warned(&self, guild_id: GuildId, role_id: RoleId) -> Result<()>;
+
+    /// owned roles in every guild that expired at or before `now`
+    async fn expired_roles(&self, now: i64) -> Result<Vec<ExpiringRole>>;
+
+    /// remove the owner of a role if it is still expired at `now`,
+    /// returns true on success
+    async fn orphan_expired(&self, guild_id: GuildId, role_id: RoleId, now: i64) -> Result<bool>;
+
+    /// delete a role if it is still expired at `now`,
+    /// returns true on success
+    async fn delete_expired(&self, guild_id: GuildId, role_id: RoleId, now: i64) -> Result<bool>;
+
+    async fn query_role(&self, guild_id: GuildId, role_id: RoleId) -> Result<QueryRoleResult>;
+
+    /// returns true on success
+    async fn add_role(&self, guild_id: GuildId, role_id: RoleId, user_id: UserId) -> Result<bool>;
+
+    /// returns true on success
+    async fn remove_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+        caller_user_id: UserId,
+    ) -> Result<bool>;
+
+    /// remember that a role owner left the guild at `left_at`,
+    /// an earlier departure is kept
+    async fn record_departure(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        left_at: i64,
+    ) -> Result<()>;
+
+    /// forget the departure of a user who came back,
+    /// returns true if there was one
+    async fn cancel_departure(&self, guild_id: GuildId, user_id: UserId) -> Result<bool>;
+
+    /// departures in every guild that happened at or before `before`
+    async fn departures(&self, before: i64) -> Result<Vec<(GuildId, UserId)>>;
+
+    /// remove the owner from every role of a user who left at or before `before`,
+    /// returns the orphaned roles, none if the departure was cancelled
+    async fn orphan_departed(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        before: i64,
+    ) -> Result<Vec<RoleId>>;
+
+    /// delete every role of a user who left at or before `before`,
+    /// returns the deleted roles, none if the departure was cancelled
+    async fn delete_departed(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        before: i64,
+    ) -> Result<Vec<RoleId>>;
+
+    /// 0 if the user is not known
+    async fn balance(&self, guild_id: GuildId, user_id: UserId) -> Result<i64>;
+
+    /// creates the user if needed
+    async fn deposit(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        amount: i64,
+        reason: LedgerReason,
+    ) -> Result<()>;
+
+    /// fails if the balance is less than `amount`,
+    /// returns true on success
+    async fn withdraw(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        amount: i64,
+        reason: LedgerReason,
+    ) -> Result<bool>;
+
+    /// move `amount` from one user to another,
+    /// fails if the sender balance is less than `amount`,
+    /// returns true on success
+    async fn transfer(
+        &self,
+        guild_id: GuildId,
+        from_user_id: UserId,
+        to_user_id: UserId,
+        amount: i64,
+    ) -> Result<bool>;
+
+    /// how many ledger entries the user has,
+    /// message rewards only count with `rewards`
+    async fn ledger_count(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        rewards: bool,
+    ) -> Result<usize>;
+
+    /// ledger entries of the user, newest first,
+    /// message rewards are only included with `rewards`
+    async fn ledger(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        rewards: bool,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<LedgerEntry>>;
+
+    /// every managed role of a guild with its name
+    async fn guild_roles(&self, guild_id: GuildId) -> Result<Vec<(RoleId, String)>>;
+
+    async fn guild_users(&self, guild_id: GuildId) -> Result<Vec<UserId>>;
+
+    /// every managed role assignment of a guild
+    async fn guild_user_roles(&self, guild_id: GuildId) -> Result<Vec<(UserId, RoleId)>>;
+
+    /// returns how many were created
+    async fn create_users(&self, guild_id: GuildId, user_ids: &[UserId]) -> Result<usize>;
+
+    /// the users have to exist, unmanaged roles are skipped,
+    /// returns how many were added
+    async fn add_user_roles(
+        &self,
+        guild_id: GuildId,
+        user_roles: &[(UserId, RoleId)],
+    ) -> Result<usize>;
+
+    /// returns how many were removed
+    async fn remove_user_roles(
+        &self,
+        guild_id: GuildId,
+        user_roles: &[(UserId, RoleId)],
+    ) -> Result<usize>;
+
+    /// unix timestamp of the last full sync, `None` if it was never synced
+    async fn synced_at(&self, guild_id: GuildId) -> Result<Option<i64>>;
+
+    async fn set_synced_at(&self, guild_id: GuildId, synced_at: i64) -> Result<()>;
+
+    /// mark when the bot was removed from a guild, `None` once it is back
+    async fn set_guild_left(&self, guild_id: GuildId, left_at: Option<i64>) -> Result<()>;
+
+    /// guilds the bot was removed from at or before `before`
+    async fn left_guilds(&self, before: i64) -> Result<Vec<GuildId>>;
+
+    /// delete everything of a guild the bot was removed from at or before `before`,
+    /// returns true if it was deleted, false if the bot came back
+    async fn purge_guild(&self, guild_id: GuildId, before: i64) -> Result<bool>;
+
+    /// managed roles the user has in one guild
+    async fn member_roles(&self, guild_id: GuildId, user_id: UserId) -> Result<Vec<RoleId>>;
+
+    /// give roles to a user, creates the user if needed,
+    /// unmanaged roles are skipped,
+    /// returns how many were added
+    async fn add_member_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_ids: &[RoleId],
+    ) -> Result<usize>;
+
+    /// returns how many were removed
+    async fn remove_member_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_ids: &[RoleId],
+    ) -> Result<usize>;
+
+    /// managed roles the user has in one guild that are restored on rejoin,
+    /// the ones marked sticky, and with `!sticky_only` also the unmarked ones
+    async fn restored_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        sticky_only: bool,
+    ) -> Result<Vec<RoleId>>;
+
+    /// also creates the guild if the bot never saw it join
+    async fn set_main_channel(&self, guild_id: GuildId, channel_id: ChannelId) -> Result<()>;
+
+    async fn get_main_channel(&self, guild_id: GuildId) -> Result<Option<ChannelId>>;
+
+    /// settings of a guild, all `None` if they were never set
+    async fn guild_settings(&self, guild_id: GuildId) -> Result<GuildSettings>;
+
+    async fn set_guild_settings(&self, guild_id: GuildId, settings: &GuildSettings) -> Result<()>;
+
+    /// per discord role `max_owned_roles` overrides, 0 is unlimited
+    async fn role_limits(&self, guild_id: GuildId) -> Result<Vec<(RoleId, u32)>>;
+
+    /// `None` removes the override
+    async fn set_role_limit(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        max_owned_roles: Option<u32>,
+    ) -> Result<()>;
+}
@@ -0,0 +1,1112 @@
+use std::collections::{HashMap, HashSet};
+
+use color_eyre::eyre::{Result, eyre};
+use dashmap::DashMap;
+use serenity::{
+    all::{ChannelId, GuildId, RoleId, UserId},
+    async_trait,
+};
+
+use super::{
+    ExpiringRole, GuildSettings, LedgerEntry, LedgerReason, QueryRoleResult, RoleStore, SCHEMA,
+};
+use crate::expiry;
+
+//
+
+/// non-persistent store for tests and local development
+///
+/// every guild is locked as a whole while it is being modified,
+/// so each method is atomic like the SQL queries
+#[derive(Default)]
+pub struct MemoryStore {
+    guilds: DashMap<GuildId, Guild>,
+}
+
+#[derive(Default)]
+struct Guild {
+    main_channel_id: Option<ChannelId>,
+    synced_at: Option<i64>,
+    /// when the bot was removed from the guild
+    left_at: Option<i64>,
+    settings: GuildSettings,
+    role_limits: HashMap<RoleId, u32>,
+    roles: HashMap<RoleId, Role>,
+    /// user balances
+    users: HashMap<UserId, i64>,
+    user_roles: HashSet<(UserId, RoleId)>,
+    /// oldest first
+    ledger: Vec<LedgerEntry>,
+    /// when each departed role owner left
+    departures: HashMap<UserId, i64>,
+}
+
+struct Role {
+    name: String,
+    owner_user_id: Option<UserId>,
+    expires_at: Option<i64>,
+    expiry_warned: bool,
+    sticky: Option<bool>,
+}
+
+impl Guild {
+    fn owned_count(&self, user_id: UserId) -> usize {
+        self.roles
+            .values()
+            .filter(|role| role.owner_user_id == Some(user_id))
+            .count()
+    }
+
+    fn at_limit(&self, user_id: UserId, max_owned: Option<u32>) -> bool {
+        max_owned.is_some_and(|max| self.owned_count(user_id) >= max as usize)
+    }
+
+    /// see [`RoleStore::withdraw`]
+    fn withdraw(&mut self, user_id: UserId, amount: i64, reason: LedgerReason) -> bool {
+        // free, even if the user is not known
+        if amount == 0 {
+            return true;
+        }
+
+        match self.users.get_mut(&user_id) {
+            Some(balance) if *balance >= amount => {
+                *balance -= amount;
+            }
+            _ => return false,
+        }
+
+        self.record(user_id, -amount, reason);
+        true
+    }
+
+    /// see [`RoleStore::deposit`]
+    fn deposit(&mut self, user_id: UserId, amount: i64, reason: LedgerReason) {
+        *self.users.entry(user_id).or_insert(0) += amount;
+        if amount != 0 {
+            self.record(user_id, amount, reason);
+        }
+    }
+
+    fn record(&mut self, user_id: UserId, amount: i64, reason: LedgerReason) {
+        self.ledger.push(LedgerEntry {
+            user_id,
+            amount,
+            reason,
+            created_at: expiry::now(),
+        });
+    }
+
+    /// remove a departure that happened at or before `before`,
+    /// returns false if there was none
+    fn take_departure(&mut self, user_id: UserId, before: i64) -> bool {
+        if self
+            .departures
+            .get(&user_id)
+            .is_some_and(|left_at| *left_at <= before)
+        {
+            self.departures.remove(&user_id);
+            return true;
+        }
+        false
+    }
+
+    fn owner(&self, role_id: RoleId) -> Option<UserId> {
+        self.roles.get(&role_id).and_then(|role| role.owner_user_id)
+    }
+
+    /// same as the `UNIQUE (guild_id, name)` + primary key conflicts
+    fn conflicts(&self, role_id: RoleId, name: &str) -> bool {
+        self.roles.contains_key(&role_id) || self.roles.values().any(|role| role.name == name)
+    }
+
+    fn insert_role(
+        &mut self,
+        role_id: RoleId,
+        name: &str,
+        owner_user_id: Option<UserId>,
+        expires_at: Option<i64>,
+    ) {
+        self.roles.insert(
+            role_id,
+            Role {
+                name: name.to_string(),
+                owner_user_id,
+                expires_at,
+                expiry_warned: false,
+                sticky: None,
+            },
+        );
+    }
+
+    fn expired(&self, role_id: RoleId, now: i64) -> bool {
+        self.roles
+            .get(&role_id)
+            .and_then(|role| role.expires_at)
+            .is_some_and(|expires_at| expires_at <= now)
+    }
+
+    fn names(&self, owner_user_id: Option<UserId>) -> Vec<String> {
+        self.roles
+            .values()
+            .filter(|role| role.owner_user_id == owner_user_id)
+            .map(|role| role.name.clone())
+            .collect()
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn guild_mut(
+        &self,
+        guild_id: GuildId,
+    ) -> Result<dashmap::mapref::one::RefMut<'_, GuildId, Guild>> {
+        self.guilds
+            .get_mut(&guild_id)
+            .ok_or_else(|| eyre!("foreign key violation: guild {guild_id} does not exist"))
+    }
+
+    /// owned roles in every guild that expire at or before `before`
+    fn expiring(&self, before: i64, include_warned: bool) -> Vec<ExpiringRole> {
+        self.guilds
+            .iter()
+            .flat_map(|guild| {
+                guild
+                    .roles
+                    .iter()
+                    .filter(|(_, role)| include_warned || !role.expiry_warned)
+                    .filter_map(|(role_id, role)| {
+                        Some(ExpiringRole {
+                            guild_id: *guild.key(),
+                            role_id: *role_id,
+                            owner_user_id: role.owner_user_id?,
+                            expires_at: role.expires_at.filter(|at| *at <= before)?,
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl RoleStore for MemoryStore {
+    async fn migrate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn columns(&self) -> Result<Vec<(String, String)>> {
+        Ok(SCHEMA
+            .iter()
+            .flat_map(|(table, columns)| {
+                columns
+                    .iter()
+                    .map(|column| (table.to_string(), column.to_string()))
+            })
+            .collect())
+    }
+
+    async fn create_guild(&self, guild_id: GuildId) -> Result<bool> {
+        let mut created = false;
+        self.guilds.entry(guild_id).or_insert_with(|| {
+            created = true;
+            Guild::default()
+        });
+        Ok(created)
+    }
+
+    async fn upsert_role(&self, guild_id: GuildId, role_id: RoleId, name: &str) -> Result<()> {
+        let mut guild = self.guild_mut(guild_id)?;
+        if guild
+            .roles
+            .iter()
+            .any(|(id, role)| *id != role_id && role.name == name)
+        {
+            return Err(eyre!("unique violation: role name {name} already exists"));
+        }
+
+        if let Some(role) = guild.roles.get_mut(&role_id) {
+            role.name = name.to_string();
+        } else {
+            guild.insert_role(role_id, name, None, None);
+        }
+        Ok(())
+    }
+
+    async fn forget_role(&self, guild_id: GuildId, role_id: RoleId) -> Result<bool> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(false);
+        };
+
+        if guild.roles.remove(&role_id).is_none() {
+            return Ok(false);
+        }
+        guild.user_roles.retain(|(_, id)| *id != role_id);
+        Ok(true)
+    }
+
+    async fn create_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        name: &str,
+        owner_user_id: UserId,
+        max_owned: Option<u32>,
+        expires_at: Option<i64>,
+        cost: i64,
+    ) -> Result<bool> {
+        let mut guild = self.guild_mut(guild_id)?;
+        if guild.at_limit(owner_user_id, max_owned) || guild.conflicts(role_id, name) {
+            return Ok(false);
+        }
+        if !guild.withdraw(owner_user_id, cost, LedgerReason::CreateRole { role_id }) {
+            return Ok(false);
+        }
+
+        guild.insert_role(role_id, name, Some(owner_user_id), expires_at);
+        Ok(true)
+    }
+
+    async fn delete_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(false);
+        };
+
+        if guild.owner(role_id) != Some(user_id) {
+            return Ok(false);
+        }
+
+        guild.roles.remove(&role_id);
+        guild.user_roles.retain(|(_, id)| *id != role_id);
+        Ok(true)
+    }
+
+    async fn abandon_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(false);
+        };
+
+        match guild.roles.get_mut(&role_id) {
+            Some(role) if role.owner_user_id == Some(user_id) => {
+                role.owner_user_id = None;
+                role.expires_at = None;
+                role.expiry_warned = false;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn set_sticky(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        owner_user_id: Option<UserId>,
+        sticky: Option<bool>,
+    ) -> Result<bool> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(false);
+        };
+
+        match guild.roles.get_mut(&role_id) {
+            Some(role) if owner_user_id.is_none() || role.owner_user_id == owner_user_id => {
+                role.sticky = sticky;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn list_count(&self, guild_id: GuildId, user_id: UserId) -> Result<usize> {
+        Ok(self
+            .guilds
+            .get(&guild_id)
+            .map_or(0, |guild| guild.owned_count(user_id)))
+    }
+
+    async fn list(&self, guild_id: GuildId, user_id: UserId) -> Result<Vec<String>> {
+        Ok(self
+            .guilds
+            .get(&guild_id)
+            .map_or_else(Vec::new, |guild| guild.names(Some(user_id))))
+    }
+
+    async fn orphaned_count(&self, guild_id: GuildId) -> Result<usize> {
+        Ok(self.orphaned(guild_id).await?.len())
+    }
+
+    async fn orphaned(&self, guild_id: GuildId) -> Result<Vec<String>> {
+        Ok(self
+            .guilds
+            .get(&guild_id)
+            .map_or_else(Vec::new, |guild| guild.names(None)))
+    }
+
+    async fn take_ownership(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+        max_owned: Option<u32>,
+        expires_at: Option<i64>,
+    ) -> Result<bool> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(false);
+        };
+
+        if guild.at_limit(user_id, max_owned) {
+            return Ok(false);
+        }
+
+        match guild.roles.get_mut(&role_id) {
+            Some(role) if role.owner_user_id.is_none() => {
+                role.owner_user_id = Some(user_id);
+                role.expires_at = expires_at;
+                role.expiry_warned = false;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn give_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        from_user_id: UserId,
+        to_user_id: UserId,
+        max_owned: Option<u32>,
+    ) -> Result<bool> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(false);
+        };
+
+        if guild.at_limit(to_user_id, max_owned) {
+            return Ok(false);
+        }
+
+        match guild.roles.get_mut(&role_id) {
+            Some(role) if role.owner_user_id == Some(from_user_id) => {
+                role.owner_user_id = Some(to_user_id);
+                role.expiry_warned = false;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn extend_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+        now: i64,
+        due_before: i64,
+        lifetime: i64,
+        cost: i64,
+    ) -> Result<Option<i64>> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(None);
+        };
+
+        let Some(expires_at) = guild
+            .roles
+            .get(&role_id)
+            .filter(|role| role.owner_user_id == Some(user_id))
+            .and_then(|role| role.expires_at)
+            .filter(|expires_at| *expires_at <= due_before)
+        else {
+            return Ok(None);
+        };
+
+        if !guild.withdraw(user_id, cost, LedgerReason::ExtendRole { role_id }) {
+            return Ok(None);
+        }
+
+        let expires_at = expires_at.max(now).saturating_add(lifetime);
+        if let Some(role) = guild.roles.get_mut(&role_id) {
+            role.expires_at = Some(expires_at);
+            role.expiry_warned = false;
+        }
+        Ok(Some(expires_at))
+    }
+
+    async fn expiring_roles(&self, before: i64) -> Result<Vec<ExpiringRole>> {
+        Ok(self.expiring(before, false))
+    }
+
+    async fn set_expiry_warned(&self, guild_id: GuildId, role_id: RoleId) -> Result<()> {
+        if let Some(mut guild) = self.guilds.get_mut(&guild_id)
+            && let Some(role) = guild.roles.get_mut(&role_id)
+        {
+            role.expiry_warned = true;
+        }
+        Ok(())
+    }
+
+    async fn expired_roles(&self, now: i64) -> Result<Vec<ExpiringRole>> {
+        Ok(self.expiring(now, true))
+    }
+
+    async fn orphan_expired(&self, guild_id: GuildId, role_id: RoleId, now: i64) -> Result<bool> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(false);
+        };
+
+        if !guild.expired(role_id, now) {
+            return Ok(false);
+        }
+
+        let Some(role) = guild.roles.get_mut(&role_id) else {
+            return Ok(false);
+        };
+        role.owner_user_id = None;
+        role.expires_at = None;
+        role.expiry_warned = false;
+        Ok(true)
+    }
+
+    async fn delete_expired(&self, guild_id: GuildId, role_id: RoleId, now: i64) -> Result<bool> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(false);
+        };
+
+        if !guild.expired(role_id, now) {
+            return Ok(false);
+        }
+
+        guild.roles.remove(&role_id);
+        guild.user_roles.retain(|(_, id)| *id != role_id);
+        Ok(true)
+    }
+
+    async fn query_role(&self, guild_id: GuildId, role_id: RoleId) -> Result<QueryRoleResult> {
+        let owner = self
+            .guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.roles.get(&role_id).map(|role| role.owner_user_id));
+
+        match owner {
+            Some(Some(user_id)) => Ok(QueryRoleResult::Owned(user_id)),
+            Some(None) => Ok(QueryRoleResult::Orphan),
+            None => Ok(QueryRoleResult::NotFound),
+        }
+    }
+
+    async fn add_role(&self, guild_id: GuildId, role_id: RoleId, user_id: UserId) -> Result<bool> {
+        let mut guild = self.guild_mut(guild_id)?;
+        if !guild.users.contains_key(&user_id) {
+            return Err(eyre!(
+                "foreign key violation: user {user_id} does not exist"
+            ));
+        }
+        if !guild.roles.contains_key(&role_id) {
+            return Err(eyre!(
+                "foreign key violation: role {role_id} does not exist"
+            ));
+        }
+
+        Ok(guild.user_roles.insert((user_id, role_id)))
+    }
+
+    async fn remove_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+        caller_user_id: UserId,
+    ) -> Result<bool> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(false);
+        };
+
+        // cant remove roles from self, except if it is owned
+        // => can remove if it is owned, or not self
+        if user_id == caller_user_id && guild.owner(role_id) != Some(caller_user_id) {
+            return Ok(false);
+        }
+
+        Ok(guild.user_roles.remove(&(user_id, role_id)))
+    }
+
+    async fn guild_roles(&self, guild_id: GuildId) -> Result<Vec<(RoleId, String)>> {
+        Ok(self.guilds.get(&guild_id).map_or_else(Vec::new, |guild| {
+            guild
+                .roles
+                .iter()
+                .map(|(role_id, role)| (*role_id, role.name.clone()))
+                .collect()
+        }))
+    }
+
+    async fn guild_users(&self, guild_id: GuildId) -> Result<Vec<UserId>> {
+        Ok(self
+            .guilds
+            .get(&guild_id)
+            .map_or_else(Vec::new, |guild| guild.users.keys().copied().collect()))
+    }
+
+    async fn guild_user_roles(&self, guild_id: GuildId) -> Result<Vec<(UserId, RoleId)>> {
+        Ok(self
+            .guilds
+            .get(&guild_id)
+            .map_or_else(Vec::new, |guild| guild.user_roles.iter().copied().collect()))
+    }
+
+    async fn create_users(&self, guild_id: GuildId, user_ids: &[UserId]) -> Result<usize> {
+        let mut guild = self.guild_mut(guild_id)?;
+
+        let mut created = 0;
+        for user_id in user_ids {
+            if !guild.users.contains_key(user_id) {
+                guild.users.insert(*user_id, 0);
+                created += 1;
+            }
+        }
+        Ok(created)
+    }
+
+    async fn add_user_roles(
+        &self,
+        guild_id: GuildId,
+        user_roles: &[(UserId, RoleId)],
+    ) -> Result<usize> {
+        let mut guild = self.guild_mut(guild_id)?;
+        if let Some((user_id, _)) = user_roles
+            .iter()
+            .find(|(user_id, _)| !guild.users.contains_key(user_id))
+        {
+            return Err(eyre!(
+                "foreign key violation: user {user_id} does not exist"
+            ));
+        }
+
+        let mut added = 0;
+        for (user_id, role_id) in user_roles {
+            if guild.roles.contains_key(role_id) && guild.user_roles.insert((*user_id, *role_id)) {
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    async fn remove_user_roles(
+        &self,
+        guild_id: GuildId,
+        user_roles: &[(UserId, RoleId)],
+    ) -> Result<usize> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(0);
+        };
+
+        Ok(user_roles
+            .iter()
+            .filter(|user_role| guild.user_roles.remove(user_role))
+            .count())
+    }
+
+    async fn synced_at(&self, guild_id: GuildId) -> Result<Option<i64>> {
+        Ok(self.guilds.get(&guild_id).and_then(|guild| guild.synced_at))
+    }
+
+    async fn set_synced_at(&self, guild_id: GuildId, synced_at: i64) -> Result<()> {
+        if let Some(mut guild) = self.guilds.get_mut(&guild_id) {
+            guild.synced_at = Some(synced_at);
+        }
+        Ok(())
+    }
+
+    async fn set_guild_left(&self, guild_id: GuildId, left_at: Option<i64>) -> Result<()> {
+        if let Some(mut guild) = self.guilds.get_mut(&guild_id) {
+            guild.left_at = left_at;
+        }
+        Ok(())
+    }
+
+    async fn left_guilds(&self, before: i64) -> Result<Vec<GuildId>> {
+        Ok(self
+            .guilds
+            .iter()
+            .filter(|guild| guild.left_at.is_some_and(|left_at| left_at <= before))
+            .map(|guild| *guild.key())
+            .collect())
+    }
+
+    async fn purge_guild(&self, guild_id: GuildId, before: i64) -> Result<bool> {
+        Ok(self
+            .guilds
+            .remove_if(&guild_id, |_, guild| {
+                guild.left_at.is_some_and(|left_at| left_at <= before)
+            })
+            .is_some())
+    }
+
+    async fn member_roles(&self, guild_id: GuildId, user_id: UserId) -> Result<Vec<RoleId>> {
+        Ok(self.guilds.get(&guild_id).map_or_else(Vec::new, |guild| {
+            guild
+                .user_roles
+                .iter()
+                .filter(|(id, _)| *id == user_id)
+                .map(|(_, role_id)| *role_id)
+                .collect()
+        }))
+    }
+
+    async fn add_member_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_ids: &[RoleId],
+    ) -> Result<usize> {
+        let mut guild = self.guild_mut(guild_id)?;
+        guild.users.entry(user_id).or_insert(0);
+
+        let mut added = 0;
+        for role_id in role_ids {
+            if guild.roles.contains_key(role_id) && guild.user_roles.insert((user_id, *role_id)) {
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    async fn remove_member_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_ids: &[RoleId],
+    ) -> Result<usize> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(0);
+        };
+
+        Ok(role_ids
+            .iter()
+            .filter(|role_id| guild.user_roles.remove(&(user_id, **role_id)))
+            .count())
+    }
+
+    async fn restored_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        sticky_only: bool,
+    ) -> Result<Vec<RoleId>> {
+        Ok(self.guilds.get(&guild_id).map_or_else(Vec::new, |guild| {
+            guild
+                .user_roles
+                .iter()
+                .filter(|(id, role_id)| {
+                    *id == user_id
+                        && guild
+                            .roles
+                            .get(role_id)
+                            .is_some_and(|role| role.sticky.unwrap_or(!sticky_only))
+                })
+                .map(|(_, role_id)| *role_id)
+                .collect()
+        }))
+    }
+
+    async fn record_departure(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        left_at: i64,
+    ) -> Result<()> {
+        self.guild_mut(guild_id)?
+            .departures
+            .entry(user_id)
+            .or_insert(left_at);
+        Ok(())
+    }
+
+    async fn cancel_departure(&self, guild_id: GuildId, user_id: UserId) -> Result<bool> {
+        Ok(self
+            .guilds
+            .get_mut(&guild_id)
+            .is_some_and(|mut guild| guild.departures.remove(&user_id).is_some()))
+    }
+
+    async fn departures(&self, before: i64) -> Result<Vec<(GuildId, UserId)>> {
+        Ok(self
+            .guilds
+            .iter()
+            .flat_map(|guild| {
+                guild
+                    .departures
+                    .iter()
+                    .filter(|(_, left_at)| **left_at <= before)
+                    .map(|(user_id, _)| (*guild.key(), *user_id))
+                    .collect::<Vec<_>>()
+            })
+            .collect())
+    }
+
+    async fn orphan_departed(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        before: i64,
+    ) -> Result<Vec<RoleId>> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(Vec::new());
+        };
+
+        if !guild.take_departure(user_id, before) {
+            return Ok(Vec::new());
+        }
+
+        let mut orphaned = Vec::new();
+        for (role_id, role) in guild.roles.iter_mut() {
+            if role.owner_user_id == Some(user_id) {
+                role.owner_user_id = None;
+                role.expires_at = None;
+                role.expiry_warned = false;
+                orphaned.push(*role_id);
+            }
+        }
+        Ok(orphaned)
+    }
+
+    async fn delete_departed(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        before: i64,
+    ) -> Result<Vec<RoleId>> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(Vec::new());
+        };
+
+        if !guild.take_departure(user_id, before) {
+            return Ok(Vec::new());
+        }
+
+        let deleted: Vec<RoleId> = guild
+            .roles
+            .iter()
+            .filter(|(_, role)| role.owner_user_id == Some(user_id))
+            .map(|(role_id, _)| *role_id)
+            .collect();
+        for role_id in &deleted {
+            guild.roles.remove(role_id);
+        }
+        guild
+            .user_roles
+            .retain(|(_, role_id)| !deleted.contains(role_id));
+        Ok(deleted)
+    }
+
+    async fn balance(&self, guild_id: GuildId, user_id: UserId) -> Result<i64> {
+        Ok(self
+            .guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.users.get(&user_id).copied())
+            .unwrap_or(0))
+    }
+
+    async fn deposit(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        amount: i64,
+        reason: LedgerReason,
+    ) -> Result<()> {
+        self.guild_mut(guild_id)?.deposit(user_id, amount, reason);
+        Ok(())
+    }
+
+    async fn withdraw(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        amount: i64,
+        reason: LedgerReason,
+    ) -> Result<bool> {
+        let Some(mut guild) = self.guilds.get_mut(&guild_id) else {
+            return Ok(false);
+        };
+
+        Ok(guild.withdraw(user_id, amount, reason))
+    }
+
+    async fn transfer(
+        &self,
+        guild_id: GuildId,
+        from_user_id: UserId,
+        to_user_id: UserId,
+        amount: i64,
+    ) -> Result<bool> {
+        let mut guild = self.guild_mut(guild_id)?;
+        let sent = LedgerReason::Transfer {
+            other_user_id: to_user_id,
+        };
+        if !guild.withdraw(from_user_id, amount, sent) {
+            return Ok(false);
+        }
+
+        let received = LedgerReason::Transfer {
+            other_user_id: from_user_id,
+        };
+        guild.deposit(to_user_id, amount, received);
+        Ok(true)
+    }
+
+    async fn ledger_count(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        rewards: bool,
+    ) -> Result<usize> {
+        Ok(self.guilds.get(&guild_id).map_or(0, |guild| {
+            guild
+                .ledger
+                .iter()
+                .filter(|entry| {
+                    entry.user_id == user_id
+                        && (rewards || !matches!(entry.reason, LedgerReason::MessageReward))
+                })
+                .count()
+        }))
+    }
+
+    async fn ledger(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        rewards: bool,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<LedgerEntry>> {
+        Ok(self.guilds.get(&guild_id).map_or_else(Vec::new, |guild| {
+            guild
+                .ledger
+                .iter()
+                .rev()
+                .filter(|entry| {
+                    entry.user_id == user_id
+                        && (rewards || !matches!(entry.reason, LedgerReason::MessageReward))
+                })
+                .skip(offset)
+                .take(limit)
+                .cloned()
+                .collect()
+        }))
+    }
+
+    async fn set_main_channel(&self, guild_id: GuildId, channel_id: ChannelId) -> Result<()> {
+        self.guilds.entry(guild_id).or_default().main_channel_id = Some(channel_id);
+        Ok(())
+    }
+
+    async fn get_main_channel(&self, guild_id: GuildId) -> Result<Option<ChannelId>> {
+        Ok(self
+            .guilds
+            .get(&guild_id)
+            .and_then(|guild| guild.main_channel_id))
+    }
+
+    async fn guild_settings(&self, guild_id: GuildId) -> Result<GuildSettings> {
+        Ok(self
+            .guilds
+            .get(&guild_id)
+            .map(|guild| guild.settings.clone())
+            .unwrap_or_default())
+    }
+
+    async fn set_guild_settings(&self, guild_id: GuildId, settings: &GuildSettings) -> Result<()> {
+        self.guild_mut(guild_id)?.settings = settings.clone();
+        Ok(())
+    }
+
+    async fn role_limits(&self, guild_id: GuildId) -> Result<Vec<(RoleId, u32)>> {
+        Ok(self.guilds.get(&guild_id).map_or_else(Vec::new, |guild| {
+            guild
+                .role_limits
+                .iter()
+                .map(|(role_id, max)| (*role_id, *max))
+                .collect()
+        }))
+    }
+
+    async fn set_role_limit(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        max_owned_roles: Option<u32>,
+    ) -> Result<()> {
+        let mut guild = self.guild_mut(guild_id)?;
+        if let Some(max_owned_roles) = max_owned_roles {
+            guild.role_limits.insert(role_id, max_owned_roles);
+        } else {
+            guild.role_limits.remove(&role_id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: GuildId = GuildId::new(1);
+    const ALICE: UserId = UserId::new(2);
+    const BOB: UserId = UserId::new(3);
+
+    async fn store() -> MemoryStore {
+        let store = MemoryStore::default();
+        store.create_guild(GUILD).await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn owned_role_cap() {
+        let store = store().await;
+        let create = |role_id, name, max_owned| {
+            store.create_role(GUILD, RoleId::new(role_id), name, ALICE, max_owned, None, 0)
+        };
+
+        assert!(create(10, "a", Some(1)).await.unwrap());
+        assert!(!create(11, "b", Some(1)).await.unwrap());
+        assert!(create(11, "b", Some(2)).await.unwrap());
+        assert!(create(12, "c", None).await.unwrap());
+        assert_eq!(store.list_count(GUILD, ALICE).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn unique_role_names() {
+        let store = store().await;
+        let (a, b) = (RoleId::new(10), RoleId::new(11));
+
+        assert!(
+            store
+                .create_role(GUILD, a, "a", ALICE, None, None, 0)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !store
+                .create_role(GUILD, b, "a", BOB, None, None, 0)
+                .await
+                .unwrap()
+        );
+        assert!(store.upsert_role(GUILD, b, "a").await.is_err());
+
+        // renaming a role to its own name is fine
+        store.upsert_role(GUILD, a, "a").await.unwrap();
+        // and a deleted role frees its name
+        assert!(store.forget_role(GUILD, a).await.unwrap());
+        assert!(
+            store
+                .create_role(GUILD, b, "a", BOB, None, None, 0)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn cascade() {
+        let store = store().await;
+        let (a, b) = (RoleId::new(10), RoleId::new(11));
+        store.upsert_role(GUILD, a, "a").await.unwrap();
+        store.upsert_role(GUILD, b, "b").await.unwrap();
+        store.add_member_roles(GUILD, ALICE, &[a, b]).await.unwrap();
+
+        // role -> user_roles
+        assert!(store.forget_role(GUILD, a).await.unwrap());
+        assert_eq!(store.member_roles(GUILD, ALICE).await.unwrap(), vec![b]);
+
+        // guild -> roles -> user_roles
+        store.set_guild_left(GUILD, Some(10)).await.unwrap();
+        assert!(store.purge_guild(GUILD, 10).await.unwrap());
+        assert!(store.guild_roles(GUILD).await.unwrap().is_empty());
+        assert!(store.guild_user_roles(GUILD).await.unwrap().is_empty());
+        assert!(store.member_roles(GUILD, ALICE).await.unwrap().is_empty());
+
+        // nothing is left to attach new rows to
+        assert!(store.upsert_role(GUILD, a, "a").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn extend_role() {
+        let store = store().await;
+        let (role, never) = (RoleId::new(10), RoleId::new(11));
+        store
+            .create_role(GUILD, role, "a", ALICE, None, Some(100), 0)
+            .await
+            .unwrap();
+        store
+            .create_role(GUILD, never, "b", ALICE, None, None, 0)
+            .await
+            .unwrap();
+        let extend = |role_id, now, warning, cost| {
+            store.extend_role(GUILD, role_id, ALICE, now, now + warning, 50, cost)
+        };
+
+        // not due yet
+        assert_eq!(extend(role, 50, 30, 0).await.unwrap(), None);
+        // deadline still ahead, the rest of it is kept
+        assert_eq!(extend(role, 80, 30, 0).await.unwrap(), Some(150));
+        // and it can't be pushed out again right away
+        assert_eq!(extend(role, 80, 30, 0).await.unwrap(), None);
+        // not enough currency
+        assert_eq!(extend(role, 200, 30, 1).await.unwrap(), None);
+        // deadline already passed, counts from now
+        assert_eq!(extend(role, 200, 30, 0).await.unwrap(), Some(250));
+
+        assert_eq!(extend(never, 200, 30, 0).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn restored_roles() {
+        let store = store().await;
+        let (sticky, not_sticky, unmarked) = (RoleId::new(10), RoleId::new(11), RoleId::new(12));
+        for (role_id, name) in [(sticky, "a"), (not_sticky, "b"), (unmarked, "c")] {
+            store.upsert_role(GUILD, role_id, name).await.unwrap();
+        }
+        store
+            .add_member_roles(GUILD, ALICE, &[sticky, not_sticky, unmarked])
+            .await
+            .unwrap();
+        assert!(
+            store
+                .set_sticky(GUILD, sticky, None, Some(true))
+                .await
+                .unwrap()
+        );
+        assert!(
+            store
+                .set_sticky(GUILD, not_sticky, None, Some(false))
+                .await
+                .unwrap()
+        );
+
+        let store = &store;
+        let restored = |sticky_only| async move {
+            let mut roles = store
+                .restored_roles(GUILD, ALICE, sticky_only)
+                .await
+                .unwrap();
+            roles.sort();
+            roles
+        };
+
+        // `On`
+        assert_eq!(restored(false).await, vec![sticky, unmarked]);
+        // `Sticky`
+        assert_eq!(restored(true).await, vec![sticky]);
+    }
+}
@@ -0,0 +1,1560 @@
+use std::str::FromStr;
+
+use color_eyre::eyre::Result;
+use serenity::{
+    all::{ChannelId, GuildId, RoleId, UserId},
+    async_trait,
+};
+use sqlx::{
+    SqliteConnection, SqlitePool,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+};
+
+use super::{
+    ExpiringRole, GuildSettings, LedgerEntry, LedgerReason, QueryRoleResult, RestoreRoles,
+    RoleStore,
+};
+
+//
+
+pub struct SqliteStore {
+    db: SqlitePool,
+}
+
+impl SqliteStore {
+    pub async fn connect(url: &str, max_connections: u32) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .foreign_keys(true);
+
+        let db = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?;
+
+        Ok(Self { db })
+    }
+}
+
+#[async_trait]
+impl RoleStore for SqliteStore {
+    async fn migrate(&self) -> Result<()> {
+        sqlx::migrate!("./migrations/sqlite").run(&self.db).await?;
+        Ok(())
+    }
+
+    async fn columns(&self) -> Result<Vec<(String, String)>> {
+        let columns = sqlx::query_as(
+            "
+SELECT m.name, p.name
+FROM sqlite_master m
+JOIN pragma_table_info(m.name) p
+WHERE m.type = 'table'
+            ",
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(columns)
+    }
+
+    async fn create_guild(&self, guild_id: GuildId) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+INSERT INTO guilds (guild_id)
+VALUES (?1)
+ON CONFLICT DO NOTHING
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("add_guild rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() == 1)
+    }
+
+    async fn upsert_role(&self, guild_id: GuildId, role_id: RoleId, name: &str) -> Result<()> {
+        let rows = sqlx::query(
+            "
+INSERT INTO roles (role_id, guild_id, name)
+VALUES (?1, ?2, ?3)
+ON CONFLICT (role_id, guild_id) DO UPDATE
+SET name = excluded.name
+        ",
+        )
+        .bind(role_id.get() as i64)
+        .bind(guild_id.get() as i64)
+        .bind(name)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("upsert_role rows affected: {}", rows.rows_affected());
+        Ok(())
+    }
+
+    async fn forget_role(&self, guild_id: GuildId, role_id: RoleId) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+DELETE FROM roles
+WHERE guild_id = ?1
+  AND role_id = ?2
+        ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(role_id.get() as i64)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("forget_role rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() != 0)
+    }
+
+    async fn create_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        name: &str,
+        owner_user_id: UserId,
+        max_owned: Option<u32>,
+        expires_at: Option<i64>,
+        cost: i64,
+    ) -> Result<bool> {
+        let mut tx = self.db.begin().await?;
+        if !debit(
+            &mut tx,
+            guild_id,
+            owner_user_id,
+            cost,
+            LedgerReason::CreateRole { role_id },
+        )
+        .await?
+        {
+            return Ok(false);
+        }
+
+        let rows = sqlx::query(
+            "
+INSERT INTO roles (role_id, guild_id, name, owner_user_id, expires_at)
+SELECT ?1, ?2, ?3, ?4, ?6
+WHERE (
+    SELECT COUNT(*)
+    FROM roles
+    WHERE owner_user_id = ?4
+      AND guild_id = ?2
+) < ?5
+ON CONFLICT DO NOTHING
+        ",
+        )
+        .bind(role_id.get() as i64)
+        .bind(guild_id.get() as i64)
+        .bind(name)
+        .bind(owner_user_id.get() as i64)
+        .bind(max_owned.map_or(i64::MAX, i64::from))
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tracing::debug!("add_role rows affected: {}", rows.rows_affected());
+        if rows.rows_affected() != 1 {
+            return Ok(false);
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn delete_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+DELETE FROM roles
+WHERE guild_id = ?1
+  AND role_id = ?2
+  AND owner_user_id = ?3
+        ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(role_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("delete_role rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() != 0)
+    }
+
+    async fn abandon_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+    ) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+UPDATE roles
+SET owner_user_id = NULL,
+    expires_at = NULL,
+    expiry_warned = FALSE
+WHERE guild_id = ?1
+  AND role_id = ?2
+  AND owner_user_id = ?3
+        ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(role_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("abandon_role rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() == 1)
+    }
+
+    async fn set_sticky(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        owner_user_id: Option<UserId>,
+        sticky: Option<bool>,
+    ) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+UPDATE roles
+SET sticky = ?4
+WHERE guild_id = ?1
+  AND role_id = ?2
+  AND (?3 IS NULL OR owner_user_id = ?3)
+        ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(role_id.get() as i64)
+        .bind(owner_user_id.map(|id| id.get() as i64))
+        .bind(sticky)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("set_sticky rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() == 1)
+    }
+
+    async fn list_count(&self, guild_id: GuildId, user_id: UserId) -> Result<usize> {
+        let (rows,) = sqlx::query_as::<_, (i64,)>(
+            "
+SELECT COUNT(*)
+FROM roles
+WHERE guild_id = ?1
+  AND owner_user_id = ?2
+        ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(rows as usize)
+    }
+
+    async fn list(&self, guild_id: GuildId, user_id: UserId) -> Result<Vec<String>> {
+        let rows: Vec<(String,)> = sqlx::query_as(
+            "
+SELECT name
+FROM roles
+WHERE guild_id = ?1
+  AND owner_user_id = ?2
+        ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(rows.into_iter().map(|(name,)| name).collect())
+    }
+
+    async fn orphaned_count(&self, guild_id: GuildId) -> Result<usize> {
+        let (rows,) = sqlx::query_as::<_, (i64,)>(
+            "
+SELECT COUNT(*)
+FROM roles
+WHERE guild_id = ?1
+  AND owner_user_id IS NULL
+        ",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(rows as usize)
+    }
+
+    async fn orphaned(&self, guild_id: GuildId) -> Result<Vec<String>> {
+        let rows: Vec<(String,)> = sqlx::query_as(
+            "
+SELECT name
+FROM roles
+WHERE guild_id = ?1
+  AND owner_user_id IS NULL
+        ",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(rows.into_iter().map(|(name,)| name).collect())
+    }
+
+    async fn take_ownership(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+        max_owned: Option<u32>,
+        expires_at: Option<i64>,
+    ) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+UPDATE roles
+SET owner_user_id = ?3,
+    expires_at = ?5,
+    expiry_warned = FALSE
+WHERE role_id = ?1
+  AND guild_id = ?2
+  AND owner_user_id IS NULL
+  AND (
+    SELECT COUNT(*)
+    FROM roles
+    WHERE owner_user_id = ?3
+      AND guild_id = ?2
+) < ?4
+        ",
+        )
+        .bind(role_id.get() as i64)
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(max_owned.map_or(i64::MAX, i64::from))
+        .bind(expires_at)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("take_role rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() == 1)
+    }
+
+    async fn give_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        from_user_id: UserId,
+        to_user_id: UserId,
+        max_owned: Option<u32>,
+    ) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+UPDATE roles
+SET owner_user_id = ?4,
+    expiry_warned = FALSE
+WHERE role_id = ?1
+  AND guild_id = ?2
+  AND owner_user_id = ?3
+  AND (
+    SELECT COUNT(*)
+    FROM roles
+    WHERE owner_user_id = ?4
+      AND guild_id = ?2
+) < ?5
+        ",
+        )
+        .bind(role_id.get() as i64)
+        .bind(guild_id.get() as i64)
+        .bind(from_user_id.get() as i64)
+        .bind(to_user_id.get() as i64)
+        .bind(max_owned.map_or(i64::MAX, i64::from))
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("give_role rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() == 1)
+    }
+
+    async fn extend_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+        now: i64,
+        due_before: i64,
+        lifetime: i64,
+        cost: i64,
+    ) -> Result<Option<i64>> {
+        let mut tx = self.db.begin().await?;
+        if !debit(
+            &mut tx,
+            guild_id,
+            user_id,
+            cost,
+            LedgerReason::ExtendRole { role_id },
+        )
+        .await?
+        {
+            return Ok(None);
+        }
+
+        // the two argument MAX is the scalar one
+        let expires_at: Option<(i64,)> = sqlx::query_as(
+            "
+UPDATE roles
+SET expires_at = MAX(expires_at, ?4) + ?6,
+    expiry_warned = FALSE
+WHERE guild_id = ?1
+  AND role_id = ?2
+  AND owner_user_id = ?3
+  AND expires_at <= ?5
+RETURNING expires_at
+        ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(role_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(now)
+        .bind(due_before)
+        .bind(lifetime)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        tracing::debug!("extend_role new expiry: {expires_at:?}");
+        let Some((expires_at,)) = expires_at else {
+            return Ok(None);
+        };
+
+        tx.commit().await?;
+        Ok(Some(expires_at))
+    }
+
+    async fn expiring_roles(&self, before: i64) -> Result<Vec<ExpiringRole>> {
+        let roles: Vec<(i64, i64, i64, i64)> = sqlx::query_as(
+            "
+SELECT guild_id, role_id, owner_user_id, expires_at
+FROM roles
+WHERE owner_user_id IS NOT NULL
+  AND expires_at <= ?1
+  AND NOT expiry_warned
+            ",
+        )
+        .bind(before)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(roles.into_iter().map(expiring_role).collect())
+    }
+
+    async fn set_expiry_warned(&self, guild_id: GuildId, role_id: RoleId) -> Result<()> {
+        let rows = sqlx::query(
+            "
+UPDATE roles
+SET expiry_warned = TRUE
+WHERE guild_id = ?1
+  AND role_id = ?2
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(role_id.get() as i64)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("set_expiry_warned rows affected: {}", rows.rows_affected());
+        Ok(())
+    }
+
+    async fn expired_roles(&self, now: i64) -> Result<Vec<ExpiringRole>> {
+        let roles: Vec<(i64, i64, i64, i64)> = sqlx::query_as(
+            "
+SELECT guild_id, role_id, owner_user_id, expires_at
+FROM roles
+WHERE owner_user_id IS NOT NULL
+  AND expires_at <= ?1
+            ",
+        )
+        .bind(now)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(roles.into_iter().map(expiring_role).collect())
+    }
+
+    async fn orphan_expired(&self, guild_id: GuildId, role_id: RoleId, now: i64) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+UPDATE roles
+SET owner_user_id = NULL,
+    expires_at = NULL,
+    expiry_warned = FALSE
+WHERE guild_id = ?1
+  AND role_id = ?2
+  AND expires_at <= ?3
+        ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(role_id.get() as i64)
+        .bind(now)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("orphan_expired rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() == 1)
+    }
+
+    async fn delete_expired(&self, guild_id: GuildId, role_id: RoleId, now: i64) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+DELETE FROM roles
+WHERE guild_id = ?1
+  AND role_id = ?2
+  AND expires_at <= ?3
+        ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(role_id.get() as i64)
+        .bind(now)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("delete_expired rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() == 1)
+    }
+
+    async fn query_role(&self, guild_id: GuildId, role_id: RoleId) -> Result<QueryRoleResult> {
+        let result: Option<(Option<i64>,)> = sqlx::query_as(
+            "
+SELECT owner_user_id
+FROM roles
+WHERE guild_id = ?1
+  AND role_id = ?2
+    ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(role_id.get() as i64)
+        .fetch_optional(&self.db)
+        .await?;
+
+        match result {
+            Some((Some(user_id),)) => Ok(QueryRoleResult::Owned(UserId::new(user_id as u64))),
+            Some((None,)) => Ok(QueryRoleResult::Orphan),
+            None => Ok(QueryRoleResult::NotFound),
+        }
+    }
+
+    async fn add_role(&self, guild_id: GuildId, role_id: RoleId, user_id: UserId) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+INSERT INTO user_roles (user_id, role_id, guild_id)
+VALUES (?1, ?2, ?3)
+ON CONFLICT DO NOTHING
+        ",
+        )
+        .bind(user_id.get() as i64)
+        .bind(role_id.get() as i64)
+        .bind(guild_id.get() as i64)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("apply_role rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() == 1)
+    }
+
+    async fn remove_role(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        user_id: UserId,
+        caller_user_id: UserId,
+    ) -> Result<bool> {
+        // cant remove roles from self, except if it is owned
+        // => can remove if it is owned, or not self
+        let rows = if user_id == caller_user_id {
+            sqlx::query(
+                "
+DELETE FROM user_roles
+WHERE guild_id = ?1
+  AND role_id = ?2
+  AND user_id = ?3
+  AND ?4 = (
+      SELECT owner_user_id
+      FROM roles
+      WHERE guild_id = ?1
+        AND role_id = ?2
+  )
+            ",
+            )
+            .bind(guild_id.get() as i64)
+            .bind(role_id.get() as i64)
+            .bind(user_id.get() as i64)
+            .bind(caller_user_id.get() as i64)
+            .execute(&self.db)
+            .await?
+        } else {
+            sqlx::query(
+                "
+DELETE FROM user_roles
+WHERE guild_id = ?1
+  AND role_id = ?2
+  AND user_id = ?3
+                ",
+            )
+            .bind(guild_id.get() as i64)
+            .bind(role_id.get() as i64)
+            .bind(user_id.get() as i64)
+            .execute(&self.db)
+            .await?
+        };
+
+        tracing::debug!("remove_role rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() != 0)
+    }
+
+    async fn ledger_count(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        rewards: bool,
+    ) -> Result<usize> {
+        let (rows,): (i64,) = sqlx::query_as(
+            "
+SELECT COUNT(*)
+FROM ledger
+WHERE guild_id = ?1
+  AND user_id = ?2
+  AND (?3 OR reason <> 'message_reward')
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(rewards)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(rows as usize)
+    }
+
+    async fn ledger(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        rewards: bool,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<LedgerEntry>> {
+        #[allow(clippy::type_complexity)]
+        let entries: Vec<(i64, i64, String, Option<i64>, Option<i64>, i64)> = sqlx::query_as(
+            "
+SELECT user_id, amount, reason, role_id, other_user_id, created_at
+FROM ledger
+WHERE guild_id = ?1
+  AND user_id = ?2
+  AND (?3 OR reason <> 'message_reward')
+ORDER BY entry_id DESC
+LIMIT ?5
+OFFSET ?4
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(rewards)
+        .bind(offset as i64)
+        .bind(limit as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        entries
+            .into_iter()
+            .map(
+                |(user_id, amount, reason, role_id, other_user_id, created_at)| {
+                    Ok(LedgerEntry {
+                        user_id: UserId::new(user_id as u64),
+                        amount,
+                        reason: LedgerReason::from_columns(&reason, role_id, other_user_id)?,
+                        created_at,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    async fn guild_roles(&self, guild_id: GuildId) -> Result<Vec<(RoleId, String)>> {
+        let roles: Vec<(i64, String)> = sqlx::query_as(
+            "
+SELECT role_id, name
+FROM roles
+WHERE guild_id = ?1
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(roles
+            .into_iter()
+            .map(|(role_id, name)| (RoleId::new(role_id as u64), name))
+            .collect())
+    }
+
+    async fn guild_users(&self, guild_id: GuildId) -> Result<Vec<UserId>> {
+        let users: Vec<(i64,)> = sqlx::query_as(
+            "
+SELECT user_id
+FROM users
+WHERE guild_id = ?1
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(users
+            .into_iter()
+            .map(|(user_id,)| UserId::new(user_id as u64))
+            .collect())
+    }
+
+    async fn guild_user_roles(&self, guild_id: GuildId) -> Result<Vec<(UserId, RoleId)>> {
+        let user_roles: Vec<(i64, i64)> = sqlx::query_as(
+            "
+SELECT user_id, role_id
+FROM user_roles
+WHERE guild_id = ?1
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(user_roles
+            .into_iter()
+            .map(|(user_id, role_id)| (UserId::new(user_id as u64), RoleId::new(role_id as u64)))
+            .collect())
+    }
+
+    async fn create_users(&self, guild_id: GuildId, user_ids: &[UserId]) -> Result<usize> {
+        let mut tx = self.db.begin().await?;
+
+        // no array parameters in sqlite
+        let mut created = 0;
+        for user_id in user_ids {
+            let rows = sqlx::query(
+                "
+INSERT INTO users (user_id, guild_id)
+VALUES (?1, ?2)
+ON CONFLICT DO NOTHING
+                ",
+            )
+            .bind(user_id.get() as i64)
+            .bind(guild_id.get() as i64)
+            .execute(&mut *tx)
+            .await?;
+            created += rows.rows_affected() as usize;
+        }
+
+        tx.commit().await?;
+
+        tracing::debug!("create_users rows affected: {created}");
+        Ok(created)
+    }
+
+    async fn add_user_roles(
+        &self,
+        guild_id: GuildId,
+        user_roles: &[(UserId, RoleId)],
+    ) -> Result<usize> {
+        let mut tx = self.db.begin().await?;
+
+        let mut added = 0;
+        for (user_id, role_id) in user_roles {
+            let rows = sqlx::query(
+                "
+INSERT INTO user_roles (user_id, guild_id, role_id)
+SELECT ?1, guild_id, role_id
+FROM roles
+WHERE guild_id = ?2
+  AND role_id = ?3
+ON CONFLICT DO NOTHING
+                ",
+            )
+            .bind(user_id.get() as i64)
+            .bind(guild_id.get() as i64)
+            .bind(role_id.get() as i64)
+            .execute(&mut *tx)
+            .await?;
+            added += rows.rows_affected() as usize;
+        }
+
+        tx.commit().await?;
+
+        tracing::debug!("add_user_roles rows affected: {added}");
+        Ok(added)
+    }
+
+    async fn remove_user_roles(
+        &self,
+        guild_id: GuildId,
+        user_roles: &[(UserId, RoleId)],
+    ) -> Result<usize> {
+        let mut tx = self.db.begin().await?;
+
+        let mut removed = 0;
+        for (user_id, role_id) in user_roles {
+            let rows = sqlx::query(
+                "
+DELETE FROM user_roles
+WHERE guild_id = ?1
+  AND user_id = ?2
+  AND role_id = ?3
+                ",
+            )
+            .bind(guild_id.get() as i64)
+            .bind(user_id.get() as i64)
+            .bind(role_id.get() as i64)
+            .execute(&mut *tx)
+            .await?;
+            removed += rows.rows_affected() as usize;
+        }
+
+        tx.commit().await?;
+
+        tracing::debug!("remove_user_roles rows affected: {removed}");
+        Ok(removed)
+    }
+
+    async fn synced_at(&self, guild_id: GuildId) -> Result<Option<i64>> {
+        let synced_at: Option<(Option<i64>,)> = sqlx::query_as(
+            "
+SELECT synced_at
+FROM guilds
+WHERE guild_id = ?1
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(synced_at.and_then(|(synced_at,)| synced_at))
+    }
+
+    async fn set_synced_at(&self, guild_id: GuildId, synced_at: i64) -> Result<()> {
+        let rows = sqlx::query(
+            "
+UPDATE guilds
+SET synced_at = ?2
+WHERE guild_id = ?1
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(synced_at)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("set_synced_at rows affected: {}", rows.rows_affected());
+        Ok(())
+    }
+
+    async fn set_guild_left(&self, guild_id: GuildId, left_at: Option<i64>) -> Result<()> {
+        let rows = sqlx::query(
+            "
+UPDATE guilds
+SET left_at = ?2
+WHERE guild_id = ?1
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(left_at)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("set_guild_left rows affected: {}", rows.rows_affected());
+        Ok(())
+    }
+
+    async fn left_guilds(&self, before: i64) -> Result<Vec<GuildId>> {
+        let guilds: Vec<(i64,)> = sqlx::query_as(
+            "
+SELECT guild_id
+FROM guilds
+WHERE left_at <= ?1
+            ",
+        )
+        .bind(before)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(guilds
+            .into_iter()
+            .map(|(guild_id,)| GuildId::new(guild_id as u64))
+            .collect())
+    }
+
+    async fn purge_guild(&self, guild_id: GuildId, before: i64) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+DELETE FROM guilds
+WHERE guild_id = ?1
+  AND left_at <= ?2
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(before)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("purge_guild rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() != 0)
+    }
+
+    async fn member_roles(&self, guild_id: GuildId, user_id: UserId) -> Result<Vec<RoleId>> {
+        let roles: Vec<(i64,)> = sqlx::query_as(
+            "
+SELECT role_id
+FROM user_roles
+WHERE guild_id = ?1
+  AND user_id = ?2
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(roles
+            .into_iter()
+            .map(|(role_id,)| RoleId::new(role_id as u64))
+            .collect())
+    }
+
+    async fn add_member_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_ids: &[RoleId],
+    ) -> Result<usize> {
+        let mut tx = self.db.begin().await?;
+        sqlx::query(
+            "
+INSERT INTO users (user_id, guild_id)
+VALUES (?1, ?2)
+ON CONFLICT DO NOTHING
+            ",
+        )
+        .bind(user_id.get() as i64)
+        .bind(guild_id.get() as i64)
+        .execute(&mut *tx)
+        .await?;
+
+        // no array parameters in sqlite
+        let mut added = 0;
+        for role_id in role_ids {
+            let rows = sqlx::query(
+                "
+INSERT INTO user_roles (user_id, guild_id, role_id)
+SELECT ?1, guild_id, role_id
+FROM roles
+WHERE guild_id = ?2
+  AND role_id = ?3
+ON CONFLICT DO NOTHING
+                ",
+            )
+            .bind(user_id.get() as i64)
+            .bind(guild_id.get() as i64)
+            .bind(role_id.get() as i64)
+            .execute(&mut *tx)
+            .await?;
+            added += rows.rows_affected() as usize;
+        }
+
+        tx.commit().await?;
+
+        tracing::debug!("add_member_roles rows affected: {added}");
+        Ok(added)
+    }
+
+    async fn remove_member_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_ids: &[RoleId],
+    ) -> Result<usize> {
+        let mut tx = self.db.begin().await?;
+
+        let mut removed = 0;
+        for role_id in role_ids {
+            let rows = sqlx::query(
+                "
+DELETE FROM user_roles
+WHERE guild_id = ?1
+  AND user_id = ?2
+  AND role_id = ?3
+                ",
+            )
+            .bind(guild_id.get() as i64)
+            .bind(user_id.get() as i64)
+            .bind(role_id.get() as i64)
+            .execute(&mut *tx)
+            .await?;
+            removed += rows.rows_affected() as usize;
+        }
+
+        tx.commit().await?;
+
+        tracing::debug!("remove_member_roles rows affected: {removed}");
+        Ok(removed)
+    }
+
+    async fn restored_roles(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        sticky_only: bool,
+    ) -> Result<Vec<RoleId>> {
+        let roles: Vec<(i64,)> = sqlx::query_as(
+            "
+SELECT user_roles.role_id
+FROM user_roles
+JOIN roles
+  ON roles.guild_id = user_roles.guild_id
+ AND roles.role_id = user_roles.role_id
+WHERE user_roles.guild_id = ?1
+  AND user_roles.user_id = ?2
+  AND COALESCE(roles.sticky, NOT ?3)
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(sticky_only)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(roles
+            .into_iter()
+            .map(|(role_id,)| RoleId::new(role_id as u64))
+            .collect())
+    }
+
+    async fn record_departure(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        left_at: i64,
+    ) -> Result<()> {
+        let rows = sqlx::query(
+            "
+INSERT INTO departures (guild_id, user_id, left_at)
+VALUES (?1, ?2, ?3)
+ON CONFLICT DO NOTHING
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .bind(left_at)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("record_departure rows affected: {}", rows.rows_affected());
+        Ok(())
+    }
+
+    async fn cancel_departure(&self, guild_id: GuildId, user_id: UserId) -> Result<bool> {
+        let rows = sqlx::query(
+            "
+DELETE FROM departures
+WHERE guild_id = ?1
+  AND user_id = ?2
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("cancel_departure rows affected: {}", rows.rows_affected());
+        Ok(rows.rows_affected() != 0)
+    }
+
+    async fn departures(&self, before: i64) -> Result<Vec<(GuildId, UserId)>> {
+        let departures: Vec<(i64, i64)> = sqlx::query_as(
+            "
+SELECT guild_id, user_id
+FROM departures
+WHERE left_at <= ?1
+            ",
+        )
+        .bind(before)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(departures
+            .into_iter()
+            .map(|(guild_id, user_id)| (GuildId::new(guild_id as u64), UserId::new(user_id as u64)))
+            .collect())
+    }
+
+    async fn orphan_departed(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        before: i64,
+    ) -> Result<Vec<RoleId>> {
+        let mut tx = self.db.begin().await?;
+        if !take_departure(&mut tx, guild_id, user_id, before).await? {
+            return Ok(Vec::new());
+        }
+
+        let roles: Vec<(i64,)> = sqlx::query_as(
+            "
+UPDATE roles
+SET owner_user_id = NULL,
+    expires_at = NULL,
+    expiry_warned = FALSE
+WHERE guild_id = ?1
+  AND owner_user_id = ?2
+RETURNING role_id
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(roles
+            .into_iter()
+            .map(|(role_id,)| RoleId::new(role_id as u64))
+            .collect())
+    }
+
+    async fn delete_departed(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        before: i64,
+    ) -> Result<Vec<RoleId>> {
+        let mut tx = self.db.begin().await?;
+        if !take_departure(&mut tx, guild_id, user_id, before).await? {
+            return Ok(Vec::new());
+        }
+
+        let roles: Vec<(i64,)> = sqlx::query_as(
+            "
+DELETE FROM roles
+WHERE guild_id = ?1
+  AND owner_user_id = ?2
+RETURNING role_id
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(roles
+            .into_iter()
+            .map(|(role_id,)| RoleId::new(role_id as u64))
+            .collect())
+    }
+
+    async fn balance(&self, guild_id: GuildId, user_id: UserId) -> Result<i64> {
+        let balance: Option<(i64,)> = sqlx::query_as(
+            "
+SELECT balance
+FROM users
+WHERE guild_id = ?1
+  AND user_id = ?2
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(user_id.get() as i64)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(balance.map_or(0, |(balance,)| balance))
+    }
+
+    async fn deposit(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        amount: i64,
+        reason: LedgerReason,
+    ) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+        credit(&mut tx, guild_id, user_id, amount, reason).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn withdraw(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        amount: i64,
+        reason: LedgerReason,
+    ) -> Result<bool> {
+        let mut tx = self.db.begin().await?;
+        if !debit(&mut tx, guild_id, user_id, amount, reason).await? {
+            return Ok(false);
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn transfer(
+        &self,
+        guild_id: GuildId,
+        from_user_id: UserId,
+        to_user_id: UserId,
+        amount: i64,
+    ) -> Result<bool> {
+        let mut tx = self.db.begin().await?;
+        let sent = LedgerReason::Transfer {
+            other_user_id: to_user_id,
+        };
+        if !debit(&mut tx, guild_id, from_user_id, amount, sent).await? {
+            return Ok(false);
+        }
+
+        let received = LedgerReason::Transfer {
+            other_user_id: from_user_id,
+        };
+        credit(&mut tx, guild_id, to_user_id, amount, received).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn set_main_channel(&self, guild_id: GuildId, channel_id: ChannelId) -> Result<()> {
+        let rows = sqlx::query(
+            "
+INSERT INTO guilds (guild_id, main_channel_id)
+VALUES (?1, ?2)
+ON CONFLICT (guild_id) DO UPDATE
+SET main_channel_id = excluded.main_channel_id
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(channel_id.get() as i64)
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("set_main_channel rows affected: {}", rows.rows_affected());
+        Ok(())
+    }
+
+    async fn get_main_channel(&self, guild_id: GuildId) -> Result<Option<ChannelId>> {
+        let channel_id: Option<(Option<i64>,)> = sqlx::query_as(
+            "
+SELECT main_channel_id
+FROM guilds
+WHERE guild_id = ?1
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_optional(&self.db)
+        .await?;
+
+        if let Some((Some(id),)) = channel_id {
+            Ok(Some(ChannelId::new(id as u64)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn guild_settings(&self, guild_id: GuildId) -> Result<GuildSettings> {
+        #[allow(clippy::type_complexity)]
+        let settings: Option<(
+            Option<i64>,
+            Option<bool>,
+            Option<bool>,
+            Option<bool>,
+            Option<bool>,
+            Option<bool>,
+            Option<bool>,
+            Option<String>,
+            Option<i64>,
+            Option<i64>,
+            Option<i64>,
+        )> = sqlx::query_as(
+            "
+SELECT max_owned_roles, anti_censor, delete_logging, u_echo, hoist, mentionable,
+    delete_departed_roles, restore_roles, role_cost, extend_cost, message_reward
+FROM guild_settings
+WHERE guild_id = ?1
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_optional(&self.db)
+        .await?;
+
+        let Some((
+            max_owned_roles,
+            anti_censor,
+            delete_logging,
+            u_echo,
+            hoist,
+            mentionable,
+            delete_departed_roles,
+            restore_roles,
+            role_cost,
+            extend_cost,
+            message_reward,
+        )) = settings
+        else {
+            return Ok(GuildSettings::default());
+        };
+
+        Ok(GuildSettings {
+            max_owned_roles: max_owned_roles.map(|n| n as u32),
+            anti_censor,
+            delete_logging,
+            u_echo,
+            hoist,
+            mentionable,
+            delete_departed_roles,
+            restore_roles: restore_roles
+                .as_deref()
+                .map(RestoreRoles::from_name)
+                .transpose()?,
+            role_cost: role_cost.map(|n| n as u32),
+            extend_cost: extend_cost.map(|n| n as u32),
+            message_reward: message_reward.map(|n| n as u32),
+        })
+    }
+
+    async fn set_guild_settings(&self, guild_id: GuildId, settings: &GuildSettings) -> Result<()> {
+        let rows = sqlx::query(
+            "
+INSERT INTO guild_settings
+    (guild_id, max_owned_roles, anti_censor, delete_logging, u_echo, hoist, mentionable,
+    delete_departed_roles, restore_roles, role_cost, extend_cost, message_reward)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+ON CONFLICT (guild_id) DO UPDATE
+SET max_owned_roles = excluded.max_owned_roles,
+    anti_censor = excluded.anti_censor,
+    delete_logging = excluded.delete_logging,
+    u_echo = excluded.u_echo,
+    hoist = excluded.hoist,
+    mentionable = excluded.mentionable,
+    delete_departed_roles = excluded.delete_departed_roles,
+    restore_roles = excluded.restore_roles,
+    role_cost = excluded.role_cost,
+    extend_cost = excluded.extend_cost,
+    message_reward = excluded.message_reward
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .bind(settings.max_owned_roles.map(|n| n as i64))
+        .bind(settings.anti_censor)
+        .bind(settings.delete_logging)
+        .bind(settings.u_echo)
+        .bind(settings.hoist)
+        .bind(settings.mentionable)
+        .bind(settings.delete_departed_roles)
+        .bind(settings.restore_roles.map(RestoreRoles::name))
+        .bind(settings.role_cost.map(|n| n as i64))
+        .bind(settings.extend_cost.map(|n| n as i64))
+        .bind(settings.message_reward.map(|n| n as i64))
+        .execute(&self.db)
+        .await?;
+
+        tracing::debug!("set_guild_settings rows affected: {}", rows.rows_affected());
+        Ok(())
+    }
+
+    async fn role_limits(&self, guild_id: GuildId) -> Result<Vec<(RoleId, u32)>> {
+        let limits: Vec<(i64, i64)> = sqlx::query_as(
+            "
+SELECT role_id, max_owned_roles
+FROM role_limits
+WHERE guild_id = ?1
+            ",
+        )
+        .bind(guild_id.get() as i64)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(limits
+            .into_iter()
+            .map(|(role_id, max_owned_roles)| (RoleId::new(role_id as u64), max_owned_roles as u32))
+            .collect())
+    }
+
+    async fn set_role_limit(
+        &self,
+        guild_id: GuildId,
+        role_id: RoleId,
+        max_owned_roles: Option<u32>,
+    ) -> Result<()> {
+        let rows = if let Some(max_owned_roles) = max_owned_roles {
+            sqlx::query(
+                "
+INSERT INTO role_limits (guild_id, role_id, max_owned_roles)
+VALUES (?1, ?2, ?3)
+ON CONFLICT (guild_id, role_id) DO UPDATE
+SET max_owned_roles = excluded.max_owned_roles
+                ",
+            )
+            .bind(guild_id.get() as i64)
+            .bind(role_id.get() as i64)
+            .bind(max_owned_roles as i64)
+            .execute(&self.db)
+            .await?
+        } else {
+            sqlx::query(
+                "
+DELETE FROM role_limits
+WHERE guild_id = ?1
+  AND role_id = ?2
+                ",
+            )
+            .bind(guild_id.get() as i64)
+            .bind(role_id.get() as i64)
+            .execute(&self.db)
+            .await?
+        };
+
+        tracing::debug!("set_role_limit rows affected: {}", rows.rows_affected());
+        Ok(())
+    }
+}
+
+fn expiring_role(
+    (guild_id, role_id, owner_user_id, expires_at): (i64, i64, i64, i64),
+) -> ExpiringRole {
+    ExpiringRole {
+        guild_id: GuildId::new(guild_id as u64),
+        role_id: RoleId::new(role_id as u64),
+        owner_user_id: UserId::new(owner_user_id as u64),
+        expires_at,
+    }
+}
+
+/// take `amount` from the balance,
+/// returns true on success
+async fn debit(
+    conn: &mut SqliteConnection,
+    guild_id: GuildId,
+    user_id: UserId,
+    amount: i64,
+    reason: LedgerReason,
+) -> Result<bool> {
+    // free, even if the user has no row yet
+    if amount == 0 {
+        return Ok(true);
+    }
+
+    let rows = sqlx::query(
+        "
+UPDATE users
+SET balance = balance - ?3
+WHERE guild_id = ?1
+  AND user_id = ?2
+  AND balance >= ?3
+        ",
+    )
+    .bind(guild_id.get() as i64)
+    .bind(user_id.get() as i64)
+    .bind(amount)
+    .execute(&mut *conn)
+    .await?;
+
+    tracing::debug!("debit rows affected: {}", rows.rows_affected());
+    if rows.rows_affected() != 1 {
+        return Ok(false);
+    }
+
+    record(conn, guild_id, user_id, -amount, reason).await?;
+    Ok(true)
+}
+
+/// add `amount` to the balance, creates the user if needed
+async fn credit(
+    conn: &mut SqliteConnection,
+    guild_id: GuildId,
+    user_id: UserId,
+    amount: i64,
+    reason: LedgerReason,
+) -> Result<()> {
+    let rows = sqlx::query(
+        "
+INSERT INTO users (user_id, guild_id, balance)
+VALUES (?1, ?2, ?3)
+ON CONFLICT (user_id, guild_id) DO UPDATE
+SET balance = users.balance + excluded.balance
+        ",
+    )
+    .bind(user_id.get() as i64)
+    .bind(guild_id.get() as i64)
+    .bind(amount)
+    .execute(&mut *conn)
+    .await?;
+
+    tracing::debug!("credit rows affected: {}", rows.rows_affected());
+    if amount != 0 {
+        record(conn, guild_id, user_id, amount, reason).await?;
+    }
+    Ok(())
+}
+
+/// append an entry to the ledger
+async fn record(
+    conn: &mut SqliteConnection,
+    guild_id: GuildId,
+    user_id: UserId,
+    amount: i64,
+    reason: LedgerReason,
+) -> Result<()> {
+    sqlx::query(
+        "
+INSERT INTO ledger (guild_id, user_id, amount, reason, role_id, other_user_id)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ",
+    )
+    .bind(guild_id.get() as i64)
+    .bind(user_id.get() as i64)
+    .bind(amount)
+    .bind(reason.kind())
+    .bind(reason.role_id().map(|id| id.get() as i64))
+    .bind(reason.other_user_id().map(|id| id.get() as i64))
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// remove a departure that happened at or before `before`,
+/// returns false if there was none
+async fn take_departure(
+    conn: &mut SqliteConnection,
+    guild_id: GuildId,
+    user_id: UserId,
+    before: i64,
+) -> Result<bool> {
+    let rows = sqlx::query(
+        "
+DELETE FROM departures
+WHERE guild_id = ?1
+  AND user_id = ?2
+  AND left_at <= ?3
+        ",
+    )
+    .bind(guild_id.get() as i64)
+    .bind(user_id.get() as i64)
+    .bind(before)
+    .execute(conn)
+    .await?;
+
+    tracing::debug!("take_departure rows affected: {}", rows.rows_affected());
+    Ok(rows.rows_affected() == 1)
+}
@@ -0,0 +1,395 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, mem,
+    ops::AddAssign,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::Result;
+use futures::{StreamExt, stream};
+use serenity::all::{Cache, GuildId, Http, Permissions, RoleId, UserId};
+use tokio::time;
+
+use crate::{Handler, expiry};
+
+//
+
+/// members per batch of writes, also how often progress is logged
+const BATCH_SIZE: usize = 1000;
+
+/// row changes of one table
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Counts {
+    pub added: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+/// what [`update_database`] changed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Report {
+    pub roles: Counts,
+    pub users: Counts,
+    pub user_roles: Counts,
+}
+
+/// how the last [`update_database`] of a guild went
+#[derive(Debug, Clone)]
+pub struct Status {
+    /// unix timestamp
+    pub finished_at: i64,
+    pub took: Duration,
+    pub dry_run: bool,
+    pub result: Result<Report, String>,
+}
+
+impl AddAssign for Counts {
+    fn add_assign(&mut self, rhs: Self) {
+        self.added += rhs.added;
+        self.removed += rhs.removed;
+        self.unchanged += rhs.unchanged;
+    }
+}
+
+impl AddAssign for Report {
+    fn add_assign(&mut self, rhs: Self) {
+        self.roles += rhs.roles;
+        self.users += rhs.users;
+        self.user_roles += rhs.user_roles;
+    }
+}
+
+impl fmt::Display for Counts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} removed, {} unchanged",
+            self.added, self.removed, self.unchanged
+        )
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "roles: {}; users: {}; user_roles: {}",
+            self.roles, self.users, self.user_roles
+        )
+    }
+}
+
+/// periodically sync every guild again, one at a time,
+/// spread evenly over `resync_interval`
+pub async fn run(handler: Arc<Handler>, cache: Arc<Cache>, http: Arc<Http>) {
+    let period = handler.config.resync_interval();
+    if period.is_zero() {
+        return;
+    }
+
+    // the startup sync just did every guild
+    time::sleep(period).await;
+
+    loop {
+        let guilds = cache.guilds();
+        let gap = period / u32::try_from(guilds.len()).unwrap_or(u32::MAX).max(1);
+
+        for guild_id in guilds {
+            let started = Instant::now();
+            match update_database(&handler, &http, guild_id).await {
+                Ok(report) => tracing::info!("resynced guild_id={guild_id}: {report}"),
+                Err(err) => tracing::error!("failed to resync guild_id={guild_id}: {err}"),
+            }
+            time::sleep(gap.saturating_sub(started.elapsed())).await;
+        }
+    }
+}
+
+/// sync every guild at startup, `sync_concurrency` at a time,
+/// guilds synced less than `sync_cooldown` ago are skipped
+///
+/// discord rate limits are handled by serenity, the concurrency
+/// keeps the member scans from starving the database pool
+pub async fn startup(handler: Arc<Handler>, http: Arc<Http>, guilds: Vec<GuildId>) {
+    let total = guilds.len();
+    let mut done = 0;
+
+    let mut jobs = stream::iter(guilds)
+        .map(|guild_id| {
+            let (handler, http) = (&handler, &http);
+            async move { (guild_id, startup_guild(handler, http, guild_id).await) }
+        })
+        .buffer_unordered(handler.config.sync_concurrency);
+
+    while let Some((guild_id, result)) = jobs.next().await {
+        done += 1;
+        match result {
+            Ok(Some(report)) if handler.config.sync_dry_run => {
+                tracing::info!("dry run of guild_id={guild_id} ({done}/{total}): {report}");
+            }
+            Ok(Some(report)) => {
+                tracing::info!("synced guild_id={guild_id} ({done}/{total}): {report}");
+            }
+            Ok(None) => {
+                tracing::info!("skipped recently synced guild_id={guild_id} ({done}/{total})");
+            }
+            Err(err) => {
+                tracing::error!("failed to update guild_id={guild_id} ({done}/{total}): {err}");
+            }
+        }
+    }
+}
+
+async fn startup_guild(
+    handler: &Handler,
+    http: &Http,
+    guild_id: GuildId,
+) -> Result<Option<Report>> {
+    let cooldown = handler.config.sync_cooldown;
+    if !handler.config.sync_dry_run
+        && cooldown != 0
+        && let Some(synced_at) = handler.db.synced_at(guild_id).await?
+        && expiry::now().saturating_sub(synced_at) < cooldown as i64
+    {
+        return Ok(None);
+    }
+
+    update_database(handler, http, guild_id).await.map(Some)
+}
+
+/// make the store match discord, see [`reconcile`],
+/// the outcome is kept for `/sync_status`
+pub async fn update_database(handler: &Handler, http: &Http, guild_id: GuildId) -> Result<Report> {
+    let started = Instant::now();
+    let result = reconcile(handler, http, guild_id).await;
+
+    handler.sync_status.insert(
+        guild_id,
+        Status {
+            finished_at: expiry::now(),
+            took: started.elapsed(),
+            dry_run: handler.config.sync_dry_run,
+            result: result.as_ref().copied().map_err(|err| err.to_string()),
+        },
+    );
+
+    result
+}
+
+/// make the store match discord
+///
+/// roles that were deleted or got permissions are forgotten,
+/// the roles of current members are added and removed to match discord,
+/// members who left keep their `user_roles` so they can get them back on rejoin,
+/// users are never removed so they keep their balance
+///
+/// members are written in batches of [`BATCH_SIZE`],
+/// with `sync_dry_run` the changes are only logged
+async fn reconcile(handler: &Handler, http: &Http, guild_id: GuildId) -> Result<Report> {
+    let dry_run = handler.config.sync_dry_run;
+    let mut report = Report::default();
+
+    if dry_run {
+        tracing::info!("dry run: nothing is written for guild {guild_id}");
+    } else {
+        handler.db.create_guild(guild_id).await?;
+    }
+
+    let roles = guild_id.roles(http).await?;
+    let managed: HashMap<RoleId, &str> = roles
+        .iter()
+        .filter(|(_, role)| role.permissions == Permissions::empty())
+        .map(|(role_id, role)| (*role_id, role.name.as_str()))
+        .collect();
+
+    {
+        // don't race `/create` between its discord role and its `roles` row
+        let lock = handler.role_lock(guild_id);
+        let _guard = lock.lock().await;
+
+        report.roles = sync_roles(handler, guild_id, &managed).await?;
+    }
+
+    let users: HashSet<UserId> = handler
+        .db
+        .guild_users(guild_id)
+        .await?
+        .into_iter()
+        .collect();
+    let mut stored: HashMap<UserId, HashSet<RoleId>> = HashMap::new();
+    for (user_id, role_id) in handler.db.guild_user_roles(guild_id).await? {
+        // rows of forgotten roles are gone with them, or would be in a dry run
+        if managed.contains_key(&role_id) {
+            stored.entry(user_id).or_default().insert(role_id);
+        }
+    }
+
+    let mut batch = Batch::default();
+    let mut synced = 0;
+    let mut members = guild_id.members_iter(http).boxed();
+    while let Some(member) = members.next().await {
+        let member = match member {
+            Ok(member) => member,
+            Err(err) => {
+                tracing::debug!("invalid member: {err}");
+                continue;
+            }
+        };
+
+        let user_id = member.user.id;
+        let current: HashSet<RoleId> = member
+            .roles
+            .iter()
+            .filter(|role_id| managed.contains_key(role_id))
+            .copied()
+            .collect();
+        let stored = stored.remove(&user_id).unwrap_or_default();
+
+        if users.contains(&user_id) {
+            report.users.unchanged += 1;
+        } else {
+            batch.users.push(user_id);
+        }
+        report.user_roles.unchanged += current.intersection(&stored).count();
+        batch.added.extend(
+            current
+                .difference(&stored)
+                .map(|role_id| (user_id, *role_id)),
+        );
+        batch.removed.extend(
+            stored
+                .difference(&current)
+                .map(|role_id| (user_id, *role_id)),
+        );
+
+        batch.members += 1;
+        if batch.members == BATCH_SIZE {
+            synced += batch.members;
+            report += write(handler, guild_id, mem::take(&mut batch)).await?;
+            tracing::info!("guild {guild_id}: {synced} members synced");
+        }
+    }
+    report += write(handler, guild_id, batch).await?;
+
+    // whatever is left belongs to members who left
+    report.user_roles.unchanged += stored.values().map(HashSet::len).sum::<usize>();
+
+    if !dry_run {
+        handler.db.set_synced_at(guild_id, expiry::now()).await?;
+    }
+
+    Ok(report)
+}
+
+async fn sync_roles(
+    handler: &Handler,
+    guild_id: GuildId,
+    managed: &HashMap<RoleId, &str>,
+) -> Result<Counts> {
+    let dry_run = handler.config.sync_dry_run;
+    let mut counts = Counts::default();
+
+    let stored: HashMap<RoleId, String> = handler
+        .db
+        .guild_roles(guild_id)
+        .await?
+        .into_iter()
+        .collect();
+
+    // forget first, a new role might reuse the name of a deleted one
+    for role_id in stored.keys() {
+        if managed.contains_key(role_id) {
+            continue;
+        }
+
+        if dry_run {
+            tracing::info!("dry run: forget role {role_id} in guild {guild_id}");
+            counts.removed += 1;
+            continue;
+        }
+
+        match handler.db.forget_role(guild_id, *role_id).await {
+            Ok(_) => counts.removed += 1,
+            Err(err) => tracing::error!("update_database forget_role error: {err}"),
+        }
+    }
+
+    for (role_id, name) in managed {
+        let renamed = match stored.get(role_id) {
+            Some(stored) if stored == name => {
+                counts.unchanged += 1;
+                continue;
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        if dry_run {
+            if renamed {
+                tracing::info!("dry run: rename role {role_id} to {name:?} in guild {guild_id}");
+                counts.unchanged += 1;
+            } else {
+                tracing::info!("dry run: add role {role_id} {name:?} in guild {guild_id}");
+                counts.added += 1;
+            }
+            continue;
+        }
+
+        match handler.db.upsert_role(guild_id, *role_id, name).await {
+            Ok(()) if renamed => counts.unchanged += 1,
+            Ok(()) => counts.added += 1,
+            Err(err) => tracing::error!("update_database upsert_role error: {err}"),
+        }
+    }
+
+    Ok(counts)
+}
+
+/// member changes, written together
+#[derive(Default)]
+struct Batch {
+    members: usize,
+    /// new users
+    users: Vec<UserId>,
+    added: Vec<(UserId, RoleId)>,
+    removed: Vec<(UserId, RoleId)>,
+}
+
+async fn write(handler: &Handler, guild_id: GuildId, batch: Batch) -> Result<Report> {
+    let mut report = Report::default();
+
+    if handler.config.sync_dry_run {
+        for user_id in &batch.users {
+            tracing::info!("dry run: add user {user_id} in guild {guild_id}");
+        }
+        for (user_id, role_id) in &batch.added {
+            tracing::info!("dry run: give role {role_id} to user {user_id} in guild {guild_id}");
+        }
+        for (user_id, role_id) in &batch.removed {
+            tracing::info!("dry run: take role {role_id} from user {user_id} in guild {guild_id}");
+        }
+
+        report.users.added = batch.users.len();
+        report.user_roles.added = batch.added.len();
+        report.user_roles.removed = batch.removed.len();
+        return Ok(report);
+    }
+
+    if !batch.users.is_empty() {
+        report.users.added = handler.db.create_users(guild_id, &batch.users).await?;
+        // the rest joined since the users were listed
+        report.users.unchanged = batch.users.len() - report.users.added;
+    }
+    if !batch.added.is_empty() {
+        report.user_roles.added = handler.db.add_user_roles(guild_id, &batch.added).await?;
+    }
+    if !batch.removed.is_empty() {
+        report.user_roles.removed = handler
+            .db
+            .remove_user_roles(guild_id, &batch.removed)
+            .await?;
+    }
+
+    Ok(report)
+}
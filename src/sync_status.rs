@@ -0,0 +1,77 @@
+use std::fmt::Write;
+
+use serenity::all::{CommandInteraction, Context, CreateCommand, GuildId, Permissions};
+
+use crate::Handler;
+
+//
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sync_status")
+        .description("Show how the last sync with discord went")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+}
+
+pub async fn run(
+    handler: &Handler,
+    _: &Context,
+    interaction: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let Some(member) = interaction.member.as_deref() else {
+        return Err("not in a guild".to_string());
+    };
+
+    let Some(permissions) = member.permissions else {
+        tracing::error!("member.permissions should always be Some in commands");
+        return Err("internal error".to_string());
+    };
+
+    if !permissions.administrator() {
+        return Err("permission denied".to_string());
+    }
+
+    let Ok(synced_at) = handler
+        .db
+        .synced_at(guild_id)
+        .await
+        .inspect_err(|err| tracing::error!("failed to get synced_at: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    let mut buf = String::new();
+    match handler.sync_status.get(&guild_id) {
+        Some(status) => {
+            let kind = if status.dry_run { "dry run" } else { "sync" };
+            _ = writeln!(
+                &mut buf,
+                "last {kind} finished <t:{}:R> and took {:.1}s",
+                status.finished_at,
+                status.took.as_secs_f32()
+            );
+            match &status.result {
+                Ok(report) => _ = writeln!(&mut buf, " - {report}"),
+                Err(err) => _ = writeln!(&mut buf, " - failed: {err}"),
+            }
+        }
+        None => _ = writeln!(&mut buf, "not synced since the bot started"),
+    }
+
+    match synced_at {
+        Some(synced_at) => _ = writeln!(&mut buf, "last complete sync <t:{synced_at}:R>"),
+        None => _ = writeln!(&mut buf, "never completely synced"),
+    }
+
+    if handler.config.resync_interval == 0 {
+        _ = writeln!(&mut buf, "periodic syncs are disabled");
+    } else {
+        _ = writeln!(
+            &mut buf,
+            "every guild is synced every {} minutes",
+            handler.config.resync_interval / 60
+        );
+    }
+
+    Ok(buf)
+}
@@ -3,7 +3,7 @@ use serenity::all::{
     ResolvedOption, ResolvedValue,
 };
 
-use crate::Handler;
+use crate::{Handler, expiry};
 
 //
 
@@ -32,8 +32,27 @@ pub async fn run(
         return Err("missing role".to_string());
     };
 
+    let member_roles = interaction
+        .member
+        .as_ref()
+        .map_or(&[][..], |member| &member.roles);
+    let Ok(max_owned) = handler
+        .max_owned_roles(guild_id, member_roles)
+        .await
+        .inspect_err(|err| tracing::error!("failed to get the owned role limit: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
     let Ok(success) = handler
-        .take_ownership(guild_id, role.id, interaction.user.id)
+        .db
+        .take_ownership(
+            guild_id,
+            role.id,
+            interaction.user.id,
+            max_owned,
+            handler.config.role_expires_at(expiry::now()),
+        )
         .await
         .inspect_err(|err| tracing::error!("failed to take ownership: {err}"))
     else {
@@ -0,0 +1,69 @@
+use serenity::all::{
+    CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, GuildId,
+    ResolvedOption, ResolvedValue,
+};
+
+use crate::Handler;
+
+//
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("transfer")
+        .description("Give role currency to another user")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::User, "user", "target user").required(true),
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "amount", "amount to give")
+                .min_int_value(1)
+                .required(true),
+        )
+}
+
+pub async fn run(
+    handler: &Handler,
+    _ctx: &Context,
+    interaction: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<String, String> {
+    let options = interaction.data.options();
+
+    let Some(ResolvedOption {
+        value: ResolvedValue::User(user, _),
+        ..
+    }) = options.first()
+    else {
+        return Err("missing target user".to_string());
+    };
+
+    let Some(ResolvedOption {
+        value: ResolvedValue::Integer(amount),
+        ..
+    }) = options.get(1)
+    else {
+        return Err("missing amount".to_string());
+    };
+
+    if *amount <= 0 {
+        return Err("invalid amount".to_string());
+    }
+
+    if user.id == interaction.user.id || user.bot {
+        return Err("invalid target user".to_string());
+    }
+
+    let Ok(success) = handler
+        .db
+        .transfer(guild_id, interaction.user.id, user.id, *amount)
+        .await
+        .inspect_err(|err| tracing::error!("failed to transfer: {err}"))
+    else {
+        return Err("internal error".to_string());
+    };
+
+    if !success {
+        return Err("not enough currency".to_string());
+    }
+
+    Ok(format!("gave {amount} to <@{}>", user.id))
+}